@@ -0,0 +1 @@
+ALTER TABLE accounts DROP COLUMN security
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE accounts ADD COLUMN security TEXT NOT NULL DEFAULT 'tls';
@@ -0,0 +1 @@
+ALTER TABLE accounts DROP COLUMN poll_interval
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE accounts ADD COLUMN poll_interval INTEGER NOT NULL DEFAULT 300;
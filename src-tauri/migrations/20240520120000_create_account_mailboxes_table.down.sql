@@ -0,0 +1 @@
+DROP TABLE IF EXISTS account_mailboxes
\ No newline at end of file
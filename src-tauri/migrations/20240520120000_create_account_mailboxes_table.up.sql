@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS account_mailboxes (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  account_id INTEGER NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  UNIQUE (account_id, name)
+);
+
+INSERT INTO account_mailboxes (account_id, name) SELECT id, mailbox FROM accounts;
@@ -0,0 +1,2 @@
+ALTER TABLE accounts DROP COLUMN auth_method;
+ALTER TABLE accounts DROP COLUMN oauth_provider;
\ No newline at end of file
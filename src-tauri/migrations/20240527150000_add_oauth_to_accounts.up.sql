@@ -0,0 +1,2 @@
+ALTER TABLE accounts ADD COLUMN auth_method TEXT NOT NULL DEFAULT 'password';
+ALTER TABLE accounts ADD COLUMN oauth_provider TEXT NULL;
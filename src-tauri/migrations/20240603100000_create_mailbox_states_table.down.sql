@@ -0,0 +1 @@
+DROP TABLE IF EXISTS mailbox_states
\ No newline at end of file
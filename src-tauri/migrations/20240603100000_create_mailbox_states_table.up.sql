@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS mailbox_states (
+  account_id INTEGER NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  mailbox TEXT NOT NULL,
+  uid_validity INTEGER NOT NULL,
+  last_uid INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (account_id, mailbox)
+);
@@ -0,0 +1 @@
+ALTER TABLE settings DROP COLUMN volume
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN volume REAL NOT NULL DEFAULT 1.0;
@@ -0,0 +1 @@
+ALTER TABLE accounts DROP COLUMN sound_path
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE accounts ADD COLUMN sound_path TEXT NULL;
@@ -0,0 +1,7 @@
+ALTER TABLE accounts ADD COLUMN sound_path TEXT NULL;
+
+UPDATE accounts SET sound_path = (
+  SELECT sound_path FROM account_preferences WHERE account_preferences.account_id = accounts.id
+);
+
+DROP TABLE IF EXISTS account_preferences
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS account_preferences (
+  account_id INTEGER PRIMARY KEY NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  muted BOOLEAN NULL,
+  sound BOOLEAN NULL,
+  preview BOOLEAN NULL,
+  sound_path TEXT NULL
+);
+
+INSERT INTO account_preferences (account_id, sound_path)
+SELECT id, sound_path FROM accounts WHERE sound_path IS NOT NULL;
+
+ALTER TABLE accounts DROP COLUMN sound_path;
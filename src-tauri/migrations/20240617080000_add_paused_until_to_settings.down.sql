@@ -0,0 +1 @@
+ALTER TABLE settings DROP COLUMN paused_until
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN paused_until INTEGER NULL;
@@ -0,0 +1,5 @@
+ALTER TABLE settings DROP COLUMN quiet_hours;
+ALTER TABLE settings DROP COLUMN quiet_start;
+ALTER TABLE settings DROP COLUMN quiet_end;
+ALTER TABLE settings DROP COLUMN quiet_days;
+ALTER TABLE settings DROP COLUMN quiet_summary
\ No newline at end of file
@@ -0,0 +1,6 @@
+ALTER TABLE settings ADD COLUMN quiet_hours BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN quiet_start TEXT NOT NULL DEFAULT '22:00';
+ALTER TABLE settings ADD COLUMN quiet_end TEXT NOT NULL DEFAULT '07:00';
+-- Bitmask of the days quiet hours start on, Monday is 1, Sunday is 64
+ALTER TABLE settings ADD COLUMN quiet_days INTEGER NOT NULL DEFAULT 127;
+ALTER TABLE settings ADD COLUMN quiet_summary BOOLEAN NOT NULL DEFAULT 1;
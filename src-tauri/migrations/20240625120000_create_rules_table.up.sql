@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS rules (
+  id INTEGER PRIMARY KEY NOT NULL,
+  -- NULL applies the rule to every account
+  account_id INTEGER NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  field TEXT NOT NULL,
+  match_type TEXT NOT NULL DEFAULT 'contains',
+  pattern TEXT NOT NULL,
+  action TEXT NOT NULL,
+  sound_path TEXT NULL,
+  position INTEGER NOT NULL DEFAULT 0,
+  enabled BOOLEAN NOT NULL DEFAULT 1
+);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS vip_senders
\ No newline at end of file
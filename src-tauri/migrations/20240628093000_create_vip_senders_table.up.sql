@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS vip_senders (
+  id INTEGER PRIMARY KEY NOT NULL,
+  -- NULL applies to every account
+  account_id INTEGER NULL REFERENCES accounts(id) ON DELETE CASCADE,
+  -- Lowercase email address or domain
+  address TEXT NOT NULL
+);
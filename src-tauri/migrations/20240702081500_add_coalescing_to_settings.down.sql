@@ -0,0 +1,2 @@
+ALTER TABLE settings DROP COLUMN coalesce_window;
+ALTER TABLE settings DROP COLUMN rate_limit
\ No newline at end of file
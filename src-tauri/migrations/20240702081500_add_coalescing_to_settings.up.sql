@@ -0,0 +1,4 @@
+-- Seconds during which new messages of an account are merged into one notification
+ALTER TABLE settings ADD COLUMN coalesce_window INTEGER NOT NULL DEFAULT 10;
+-- Maximum number of notifications per minute across accounts, 0 means no limit
+ALTER TABLE settings ADD COLUMN rate_limit INTEGER NOT NULL DEFAULT 6;
@@ -0,0 +1 @@
+UPDATE settings SET notifications = 0
\ No newline at end of file
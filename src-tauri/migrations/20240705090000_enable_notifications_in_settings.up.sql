@@ -0,0 +1,3 @@
+-- Notifications were always shown before the setting was honored,
+-- keep them on for existing installs whose settings row was seeded with false
+UPDATE settings SET notifications = 1;
@@ -0,0 +1,68 @@
+use std::net::TcpStream;
+
+use native_tls::{TlsConnector, TlsStream};
+use sha2::{Digest, Sha256};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, parse_x509_certificate};
+
+use crate::models::CertificateInfo;
+
+impl CertificateInfo {
+    /// Parse a DER encoded X.509 certificate
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = parse_x509_certificate(der).ok()?;
+        let validity = cert.validity();
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            not_before: validity.not_before.timestamp(),
+            not_after: validity.not_after.timestamp(),
+            dns_names: dns_names(&cert),
+            fingerprint: Sha256::digest(der)
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+        })
+    }
+}
+
+/// Certificate presented by the server of an established TLS connection
+pub fn peer_certificate(tls: &TlsStream<TcpStream>) -> Option<CertificateInfo> {
+    let cert = tls.peer_certificate().ok()??;
+    CertificateInfo::from_der(&cert.to_der().ok()?)
+}
+
+/// Certificate of a server whose certificate was rejected
+///
+/// A new handshake is done on `tcp` without verification, only to show the certificate.
+/// `before_handshake` runs on the plain connection, e.g. to ask for STARTTLS.
+pub fn rejected_certificate(
+    host: &str,
+    tcp: TcpStream,
+    before_handshake: impl FnOnce(&TcpStream) -> anyhow::Result<()>,
+) -> Option<CertificateInfo> {
+    before_handshake(&tcp).ok()?;
+    let tls = TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .ok()?
+        .connect(host, tcp)
+        .ok()?;
+    peer_certificate(&tls)
+}
+
+fn dns_names(cert: &X509Certificate) -> Vec<String> {
+    let Ok(Some(san)) = cert.subject_alternative_name() else {
+        return vec![];
+    };
+    san.value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::message::MessageHeaders;
+
+/// Period of the global rate limit
+const RATE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Sound played when a notification is shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    /// Custom sound, the bundled one is used when `None`
+    pub path: Option<String>,
+    pub volume: f64,
+}
+
+/// New messages of an account waiting to be notified
+#[derive(Debug, Clone)]
+pub struct Pending {
+    pub account: String,
+    pub mailboxes: Vec<String>,
+    pub count: usize,
+    /// Latest message, `None` when previews are turned off
+    pub latest: Option<MessageHeaders>,
+    pub urgent: bool,
+    pub sound: Option<Sound>,
+}
+
+impl Pending {
+    fn merge(&mut self, other: Pending) {
+        for mailbox in other.mailboxes {
+            if !self.mailboxes.contains(&mailbox) {
+                self.mailboxes.push(mailbox);
+            }
+        }
+        self.count += other.count;
+        self.latest = other.latest.or(self.latest.take());
+        self.sound = other.sound.or(self.sound.take());
+    }
+}
+
+#[derive(Debug)]
+struct Burst {
+    pending: Pending,
+    /// End of the coalescing window
+    deadline: Instant,
+}
+
+/// Merges bursts of new messages into one notification per account,
+/// and limits the number of notifications shown across accounts
+///
+/// A window starts with the first message of an account, later messages
+/// join it until it ends, so a steady stream still notifies regularly.
+/// Notifications over the rate limit wait for a free slot and keep merging
+/// the messages received meanwhile. Urgent messages are kept apart in their
+/// own burst, shown right away and regardless of the rate limit, so they never
+/// carry other mail past a pause or quiet hours.
+#[derive(Debug, Default)]
+pub struct Coalescer {
+    /// Keyed by account id and urgency
+    bursts: BTreeMap<(i64, bool), Burst>,
+    /// When the last notifications were shown, oldest first
+    shown: VecDeque<Instant>,
+    /// Notifications per `RATE_PERIOD`, 0 for no limit
+    rate_limit: usize,
+}
+
+impl Coalescer {
+    /// Update the rate limit, 0 disables it
+    pub fn set_rate_limit(&mut self, rate_limit: usize) {
+        self.rate_limit = rate_limit;
+    }
+
+    /// Queue new messages of an account
+    /// They join the burst of the account with the same urgency
+    pub fn push(&mut self, account_id: i64, pending: Pending, window: Duration, now: Instant) {
+        let key = (account_id, pending.urgent);
+        let deadline = match pending.urgent {
+            true => now,
+            false => now + window,
+        };
+
+        match self.bursts.get_mut(&key) {
+            Some(burst) => burst.pending.merge(pending),
+            None => {
+                self.bursts.insert(key, Burst { pending, deadline });
+            }
+        }
+    }
+
+    /// Notifications to show now, keyed by account id
+    pub fn due(&mut self, now: Instant) -> Vec<(i64, Pending)> {
+        while self
+            .shown
+            .front()
+            .is_some_and(|shown| now.duration_since(*shown) >= RATE_PERIOD)
+        {
+            self.shown.pop_front();
+        }
+
+        let due: Vec<(i64, bool)> = self
+            .bursts
+            .iter()
+            .filter(|(_, burst)| burst.deadline <= now)
+            .map(|(key, _)| *key)
+            .collect();
+
+        let mut notifications = vec![];
+        for (id, urgent) in due {
+            if !urgent && self.rate_limit > 0 && self.shown.len() >= self.rate_limit {
+                continue;
+            }
+            if let Some(burst) = self.bursts.remove(&(id, urgent)) {
+                self.shown.push_back(now);
+                notifications.push((id, burst.pending));
+            }
+        }
+        notifications
+    }
+
+    /// When `due` should be called next, `None` when nothing is queued
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let free_slot = match self.rate_limit {
+            0 => None,
+            limit if self.shown.len() >= limit => self
+                .shown
+                .get(self.shown.len() - limit)
+                .map(|shown| *shown + RATE_PERIOD),
+            _ => None,
+        };
+
+        self.bursts
+            .values()
+            .map(|burst| match (burst.pending.urgent, free_slot) {
+                (false, Some(slot)) => burst.deadline.max(slot),
+                _ => burst.deadline,
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    fn pending(mailbox: &str, count: usize, urgent: bool) -> Pending {
+        Pending {
+            account: "Work".to_string(),
+            mailboxes: vec![mailbox.to_string()],
+            count,
+            latest: None,
+            urgent,
+            sound: None,
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn messages_within_the_window_are_merged() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+
+        coalescer.push(1, pending("INBOX", 1, false), WINDOW, start);
+        coalescer.push(1, pending("Lists", 2, false), WINDOW, start + secs(4));
+        coalescer.push(2, pending("INBOX", 1, false), WINDOW, start + secs(4));
+
+        assert!(coalescer.due(start + secs(9)).is_empty());
+        assert_eq!(coalescer.next_deadline(), Some(start + WINDOW));
+
+        // The window of account 1 started with its first message
+        let due = coalescer.due(start + WINDOW);
+        assert_eq!(due.len(), 1);
+        let (id, merged) = &due[0];
+        assert_eq!(*id, 1);
+        assert_eq!(merged.count, 3);
+        assert_eq!(merged.mailboxes, vec!["INBOX", "Lists"]);
+
+        assert_eq!(coalescer.next_deadline(), Some(start + secs(14)));
+        assert_eq!(coalescer.due(start + secs(14)).len(), 1);
+        assert_eq!(coalescer.next_deadline(), None);
+    }
+
+    #[test]
+    fn urgent_messages_bypass_the_window() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+
+        coalescer.push(1, pending("INBOX", 2, false), WINDOW, start);
+        coalescer.push(1, pending("INBOX", 1, true), WINDOW, start + secs(1));
+        assert_eq!(coalescer.next_deadline(), Some(start + secs(1)));
+
+        // Only the urgent message is shown, the rest keeps waiting
+        let due = coalescer.due(start + secs(1));
+        assert_eq!(due.len(), 1);
+        assert!(due[0].1.urgent);
+        assert_eq!(due[0].1.count, 1);
+
+        let due = coalescer.due(start + WINDOW);
+        assert_eq!(due.len(), 1);
+        assert!(!due[0].1.urgent);
+        assert_eq!(due[0].1.count, 2);
+    }
+
+    #[test]
+    fn notifications_over_the_rate_limit_are_deferred() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        coalescer.set_rate_limit(2);
+
+        for id in 1..=3 {
+            coalescer.push(id, pending("INBOX", 1, false), WINDOW, start);
+        }
+        assert_eq!(coalescer.due(start + WINDOW).len(), 2);
+
+        // Messages received meanwhile join the deferred notification
+        coalescer.push(3, pending("INBOX", 1, false), WINDOW, start + secs(20));
+        assert!(coalescer.due(start + secs(30)).is_empty());
+
+        // Urgent messages ignore the rate limit
+        coalescer.push(4, pending("INBOX", 1, true), WINDOW, start + secs(30));
+        assert_eq!(coalescer.due(start + secs(30)).len(), 1);
+
+        let due = coalescer.due(start + WINDOW + RATE_PERIOD);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, 3);
+        assert_eq!(due[0].1.count, 2);
+    }
+
+    #[test]
+    fn next_deadline_waits_for_a_free_slot() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        coalescer.set_rate_limit(2);
+
+        coalescer.push(1, pending("INBOX", 1, false), WINDOW, start);
+        coalescer.push(2, pending("INBOX", 1, false), WINDOW, start + secs(5));
+        coalescer.due(start + WINDOW);
+        coalescer.due(start + secs(15));
+
+        // The rate window is full until the first notification is a minute old
+        coalescer.push(3, pending("INBOX", 1, false), WINDOW, start + secs(20));
+        assert_eq!(
+            coalescer.next_deadline(),
+            Some(start + WINDOW + RATE_PERIOD)
+        );
+
+        // An urgent message is due right away
+        coalescer.push(4, pending("INBOX", 1, true), WINDOW, start + secs(25));
+        assert_eq!(coalescer.next_deadline(), Some(start + secs(25)));
+        coalescer.due(start + secs(25));
+
+        // Three notifications shown, the slot frees up when the second one expires
+        assert_eq!(
+            coalescer.next_deadline(),
+            Some(start + secs(15) + RATE_PERIOD)
+        );
+    }
+
+    #[test]
+    fn no_rate_limit() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+
+        for id in 1..=10 {
+            coalescer.push(id, pending("INBOX", 1, false), Duration::ZERO, start);
+        }
+        assert_eq!(coalescer.due(start).len(), 10);
+        assert_eq!(coalescer.next_deadline(), None);
+    }
+}
@@ -1,10 +1,24 @@
+use std::time::Duration;
+
 use log::{error, info};
 use serde::Deserialize;
-use tauri::{command, State};
+use tauri::{command, AppHandle, Manager, State};
+
+use crate::{
+    async_cmd,
+    db::{account, mailbox},
+    error::Error,
+    imap::MIN_POLL_INTERVAL,
+    keychain::Keychain,
+    models::{Account, AccountStatus, AuthMethod, OAuthProviderKind, Security},
+    oauth::{self, OAuthProvider, PendingAuthorization},
+    AppState,
+};
 
-use crate::{async_cmd, db::account, error::Error, keychain::Keychain, models::Account, AppState};
+use super::send_restart_account_cmd;
 
-use super::send_restart_watcher_cmd;
+/// How long to wait for the user to complete the OAuth2 consent in the browser
+const AUTHORIZATION_TIMEOUT: Duration = Duration::from_secs(300);
 
 #[derive(Debug, Deserialize)]
 pub struct NewAccountAttrs<'a> {
@@ -16,6 +30,12 @@ pub struct NewAccountAttrs<'a> {
     pub password: &'a str,
     pub mailbox: &'a str,
     pub port: i64,
+    pub security: Security,
+    pub auth_method: AuthMethod,
+    pub oauth_provider: Option<OAuthProviderKind>,
+    pub poll_interval: i64,
+    /// Other mailboxes watched alongside `mailbox`
+    pub mailboxes: Vec<&'a str>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +46,12 @@ pub struct UpdateAccountAttrs<'a> {
     pub username: &'a str,
     pub mailbox: &'a str,
     pub port: i64,
+    pub security: Security,
+    pub auth_method: AuthMethod,
+    pub oauth_provider: Option<OAuthProviderKind>,
+    pub poll_interval: i64,
+    /// Other mailboxes watched alongside `mailbox`
+    pub mailboxes: Vec<&'a str>,
     pub password: Option<&'a str>,
 }
 
@@ -35,6 +61,8 @@ pub async fn cmd_create_account(
     attrs: NewAccountAttrs<'_>,
     state: State<'_, AppState>,
 ) -> Result<i64, Error> {
+    validate_poll_interval(attrs.poll_interval)?;
+
     let account = async_cmd!(account::create(
         account::CreateAccountAttrs {
             name: attrs.name,
@@ -44,22 +72,36 @@ pub async fn cmd_create_account(
             active: true,
             username: attrs.username,
             mailbox: attrs.mailbox,
+            security: attrs.security,
+            auth_method: attrs.auth_method,
+            oauth_provider: attrs.oauth_provider,
+            poll_interval: attrs.poll_interval,
         },
         &state.pool
     ))
     .await?;
 
-    match Keychain::new(account.id, &account.username).new_entry(attrs.password) {
-        Ok(_) => info!("New value added to keychain"),
-        Err(e) => {
-            error!("Something went wrong, deleting account entry {}", e);
-            account::delete(account.id, &state.pool)
-                .await
-                .expect("error while deleting");
+    async_cmd!(mailbox::replace(
+        account.id,
+        &watched_mailboxes(attrs.mailbox, &attrs.mailboxes),
+        &state.pool
+    ))
+    .await?;
+
+    // OAuth2 accounts store their tokens once authorized
+    if attrs.auth_method == AuthMethod::Password {
+        match Keychain::new(account.id, &account.username).new_entry(attrs.password) {
+            Ok(_) => info!("New value added to keychain"),
+            Err(e) => {
+                error!("Something went wrong, deleting account entry {}", e);
+                account::delete(account.id, &state.pool)
+                    .await
+                    .expect("error while deleting");
+            }
         }
     }
 
-    send_restart_watcher_cmd(&state.sender);
+    send_restart_account_cmd(&state.sender, account.id);
 
     Ok(account.id)
 }
@@ -76,6 +118,28 @@ pub async fn cmd_find_account(id: i64, state: State<'_, AppState>) -> Result<Acc
     async_cmd!(account::find(id, &state.pool)).await
 }
 
+/// Command to get the connection status of every watched mailbox
+/// Later transitions are emitted as `status::ACCOUNT_STATUS_EVENT` events
+#[command]
+pub async fn cmd_account_statuses(state: State<'_, AppState>) -> Result<Vec<AccountStatus>, Error> {
+    Ok(state.statuses.all())
+}
+
+/// Command to enable or disable an account
+/// The sessions of the account are started or stopped right away
+#[command]
+pub async fn cmd_set_account_active(
+    id: i64,
+    active: bool,
+    state: State<'_, AppState>,
+) -> Result<(), Error> {
+    async_cmd!(account::set_active(id, active, &state.pool)).await?;
+
+    send_restart_account_cmd(&state.sender, id);
+
+    Ok(())
+}
+
 #[command]
 pub async fn cmd_delete_account(id: i64, state: State<'_, AppState>) -> Result<(), Error> {
     let acc = async_cmd!(account::find(id, &state.pool)).await?;
@@ -87,7 +151,7 @@ pub async fn cmd_delete_account(id: i64, state: State<'_, AppState>) -> Result<(
 
     async_cmd!(account::delete(id, &state.pool)).await?;
 
-    send_restart_watcher_cmd(&state.sender);
+    send_restart_account_cmd(&state.sender, id);
 
     Ok(())
 }
@@ -98,8 +162,18 @@ pub async fn cmd_update_account(
     attrs: UpdateAccountAttrs<'_>,
     state: State<'_, AppState>,
 ) -> Result<(), Error> {
+    validate_poll_interval(attrs.poll_interval)?;
+
     let acc = async_cmd!(account::find(id, &state.pool)).await?;
 
+    let password = attrs.password.filter(|pwd| !pwd.trim().is_empty());
+    let auth_changed = acc.auth_method != attrs.auth_method;
+    if auth_changed && attrs.auth_method == AuthMethod::Password && password.is_none() {
+        return Err(Error::CustomError {
+            message: "A password is required to switch to password authentication".to_string(),
+        });
+    }
+
     async_cmd!(account::update(
         id,
         account::UpdateAccountAttrs {
@@ -109,22 +183,97 @@ pub async fn cmd_update_account(
             color: attrs.color,
             username: attrs.username,
             mailbox: attrs.mailbox,
+            security: attrs.security,
+            auth_method: attrs.auth_method,
+            oauth_provider: attrs.oauth_provider,
+            poll_interval: attrs.poll_interval,
         },
         &state.pool,
     ))
     .await?;
 
+    async_cmd!(mailbox::replace(
+        id,
+        &watched_mailboxes(attrs.mailbox, &attrs.mailboxes),
+        &state.pool
+    ))
+    .await?;
+
+    // The entry holds a password or the OAuth2 tokens, never keep it across auth methods
+    let keychain = Keychain::new(acc.id, &acc.username);
+    if auth_changed {
+        match keychain.delete_entry() {
+            Ok(_) => info!("credentials of the previous auth method deleted"),
+            Err(e) => error!("error while deleting credentials: {}", e),
+        }
+    }
+
     // Update keychain value if the user has provided a new password
-    if let Some(pwd) = attrs.password {
-        if !pwd.trim().is_empty() {
-            match Keychain::new(acc.id, &acc.username).new_entry(pwd) {
-                Ok(_) => info!("password updated"),
-                Err(_) => error!("error while updating password"),
-            }
+    if let (AuthMethod::Password, Some(pwd)) = (attrs.auth_method, password) {
+        match keychain.new_entry(pwd) {
+            Ok(_) => info!("password updated"),
+            Err(_) => error!("error while updating password"),
         }
     }
 
-    send_restart_watcher_cmd(&state.sender);
+    send_restart_account_cmd(&state.sender, id);
 
     Ok(())
 }
+
+/// Command to authorize OAuth2 accounts
+///
+/// Opens the provider consent page in the browser and waits for the redirect
+/// on a loopback address, then stores the tokens in the keychain.
+#[command]
+pub async fn cmd_authorize_account(
+    id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), Error> {
+    let acc = async_cmd!(account::find(id, &state.pool)).await?;
+
+    async_cmd!(async {
+        let provider = acc
+            .oauth_provider
+            .ok_or_else(|| anyhow::anyhow!("OAuth provider not configured"))?;
+        let pending =
+            PendingAuthorization::new(OAuthProvider::from_kind(provider), Some(&acc.username))?;
+
+        tauri::api::shell::open(&app.shell_scope(), &pending.url, None)?;
+
+        let tokens =
+            tokio::task::spawn_blocking(move || pending.wait(AUTHORIZATION_TIMEOUT)).await??;
+        oauth::store_tokens(&Keychain::new(acc.id, &acc.username), &tokens)
+    })
+    .await?;
+
+    info!("Account {} authorized", acc.username);
+    send_restart_account_cmd(&state.sender, id);
+
+    Ok(())
+}
+
+/// Reject polling intervals the watcher would not honor
+fn validate_poll_interval(poll_interval: i64) -> Result<(), Error> {
+    if poll_interval < MIN_POLL_INTERVAL as i64 {
+        return Err(Error::CustomError {
+            message: format!(
+                "The poll interval must be at least {} seconds",
+                MIN_POLL_INTERVAL
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// The main mailbox is always watched, followed by the other mailboxes without duplicates
+fn watched_mailboxes<'a>(main: &'a str, others: &[&'a str]) -> Vec<&'a str> {
+    let mut mailboxes = vec![main];
+    for name in others.iter().map(|n| n.trim()) {
+        if !name.is_empty() && !mailboxes.contains(&name) {
+            mailboxes.push(name);
+        }
+    }
+    mailboxes
+}
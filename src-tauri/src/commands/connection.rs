@@ -1,51 +1,155 @@
-use std::thread;
+use anyhow::anyhow;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{command, State, Window};
 
-use serde::Deserialize;
-use tauri::{command, Window};
-
-use crate::imap::{ConnectionDetails, Imap};
+use crate::{
+    error::Error,
+    imap::{ConnectionDetails, Imap},
+    keychain::Keychain,
+    models::{AuthMethod, ConnectionReport, OAuthProviderKind, Security, TestStage, TestStep},
+    oauth::{self, OAuthProvider},
+    watcher::Shutdown,
+    AppState,
+};
 
 const CONNECTION_TEST_EVENT: &str = "connection_test_result";
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct TestConnectionAttrs<'a> {
+pub struct TestConnectionAttrs {
     /// Imap server
-    pub server: &'a str,
+    pub server: String,
     /// Imap port
     pub port: i64,
     /// Imap username
-    pub username: &'a str,
+    pub username: String,
     /// Imap password
-    pub password: &'a str,
+    pub password: String,
     /// Mailbox name
-    pub mailbox: &'a str,
+    pub mailbox: String,
+    /// Transport security (tls, starttls or none)
+    pub security: Security,
+    /// Authentication mechanism (password, xoauth2 or oauthbearer)
+    pub auth_method: AuthMethod,
+    /// OAuth2 provider, required for OAuth2 accounts
+    pub oauth_provider: Option<OAuthProviderKind>,
+    /// Existing account id, used to load the OAuth2 tokens from the keychain
+    pub account_id: Option<i64>,
+}
+
+/// Payload of `CONNECTION_TEST_EVENT`
+#[derive(Debug, Serialize, Clone)]
+pub struct ConnectionTestResult {
+    /// Id given by the caller, several forms may test at the same time
+    pub request_id: String,
+    /// Empty when the test was cancelled
+    pub report: ConnectionReport,
+    pub cancelled: bool,
 }
 
 /// A command to verify IMAP connection.
 ///
-/// This test attempts a connection,
-/// returning "OK" if the connection is successful, or the server error message on failure.
-/// This command runs asynchronously and will emit an event when the connection test ends.
+/// This test attempts a connection stage by stage (see `TestStage`),
+/// and reports the outcome of each stage as a `ConnectionReport`,
+/// with the watch mode (IDLE or polling) when the connection is successful.
+/// The test runs on a blocking thread and emits an event with `request_id` when it ends,
+/// or right away once cancelled with `cmd_cancel_connection_test`.
 #[command]
-pub fn cmd_test_connection(attrs: TestConnectionAttrs<'_>, window: Window) {
-    let conn_details = &ConnectionDetails {
-        server: (attrs.server, attrs.port),
-        username: attrs.username,
-        password: attrs.password,
-        mailbox: attrs.mailbox,
-    };
+pub async fn cmd_test_connection(
+    request_id: String,
+    attrs: TestConnectionAttrs,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), Error> {
+    let (cancel, shutdown) = Shutdown::new();
+    if let Ok(mut tests) = state.connection_tests.lock() {
+        tests.insert(request_id.clone(), cancel);
+    }
 
-    thread::scope(|s| {
-        s.spawn(|| {
-            window
-                .emit(
-                    CONNECTION_TEST_EVENT,
-                    match Imap::test_connection(conn_details) {
-                        Ok(msg) => msg,
-                        Err(e) => e.to_string(),
-                    },
-                )
-                .unwrap();
-        });
+    let test = tokio::task::spawn_blocking({
+        let shutdown = shutdown.clone();
+        move || run_test(&attrs, shutdown)
     });
+    let outcome = tokio::select! {
+        report = test => Some(report),
+        _ = shutdown.requested() => None,
+    };
+
+    // Dropping the sender also stops a test still waiting for the server
+    if let Ok(mut tests) = state.connection_tests.lock() {
+        tests.remove(&request_id);
+    }
+
+    let result = match outcome {
+        Some(report) => ConnectionTestResult {
+            request_id,
+            report: report.map_err(|e| Error::CustomError {
+                message: e.to_string(),
+            })?,
+            cancelled: false,
+        },
+        None => {
+            info!("Connection test {} cancelled", request_id);
+            ConnectionTestResult {
+                request_id,
+                report: ConnectionReport::default(),
+                cancelled: true,
+            }
+        }
+    };
+
+    window
+        .emit(CONNECTION_TEST_EVENT, result)
+        .map_err(|e| Error::CustomError {
+            message: e.to_string(),
+        })
+}
+
+/// Command to cancel a running connection test
+#[command]
+pub fn cmd_cancel_connection_test(request_id: String, state: State<'_, AppState>) {
+    if let Ok(mut tests) = state.connection_tests.lock() {
+        tests.remove(&request_id);
+    }
+}
+
+fn run_test(attrs: &TestConnectionAttrs, shutdown: Shutdown) -> ConnectionReport {
+    match connection_secret(attrs) {
+        Ok(password) => Imap::test_connection(
+            &ConnectionDetails {
+                server: (&attrs.server, attrs.port),
+                username: &attrs.username,
+                password: &password,
+                mailbox: &attrs.mailbox,
+                security: attrs.security,
+                auth_method: attrs.auth_method,
+            },
+            shutdown,
+        ),
+        // Nothing to authenticate with, the server is not contacted
+        Err(e) => ConnectionReport {
+            steps: vec![TestStep {
+                stage: TestStage::Authentication,
+                ok: false,
+                detail: e.to_string(),
+                elapsed_ms: 0,
+            }],
+            ..Default::default()
+        },
+    }
+}
+
+/// OAuth2 accounts are tested with the access token stored for the account,
+/// other accounts with the provided password
+fn connection_secret(attrs: &TestConnectionAttrs) -> anyhow::Result<String> {
+    match (attrs.auth_method, attrs.oauth_provider, attrs.account_id) {
+        (AuthMethod::Password, _, _) => Ok(attrs.password.clone()),
+        (_, Some(provider), Some(id)) => oauth::access_token(
+            &OAuthProvider::from_kind(provider),
+            &Keychain::new(id, &attrs.username),
+        ),
+        _ => Err(anyhow!(
+            "Save and authorize the account before testing the connection"
+        )),
+    }
 }
@@ -4,12 +4,15 @@ use crate::{ChannelCmd, UnboundedChannel};
 
 pub mod account;
 pub mod connection;
+pub mod preferences;
+pub mod rules;
 pub mod settings;
+pub mod vip;
 
-/// Send a message to restart the watcher
-fn send_restart_watcher_cmd(sender: &Sender<UnboundedChannel>) {
+/// Send a message to restart the watcher sessions of an account
+fn send_restart_account_cmd(sender: &Sender<UnboundedChannel>, id: i64) {
     sender
         .clone()
-        .send((ChannelCmd::RestartWatcher, None))
+        .send((ChannelCmd::RestartAccount(id), None))
         .expect("Error while sending message");
 }
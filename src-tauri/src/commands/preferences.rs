@@ -0,0 +1,48 @@
+use log::info;
+use serde::Deserialize;
+use tauri::{command, State};
+
+use crate::{async_cmd, db::preferences, error::Error, models::AccountPreferences, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesAttrs<'a> {
+    pub muted: Option<bool>,
+    pub sound: Option<bool>,
+    pub preview: Option<bool>,
+    /// Custom notification sound, the bundled one is used when empty
+    pub sound_path: Option<&'a str>,
+}
+
+/// Command to fetch the notification preferences of an account
+#[command]
+pub async fn cmd_fetch_account_preferences(
+    account_id: i64,
+    state: State<'_, AppState>,
+) -> Result<AccountPreferences, Error> {
+    async_cmd!(preferences::find(account_id, &state.pool)).await
+}
+
+/// Command to update the notification preferences of an account
+/// Preferences are read on every notification, running sessions are not restarted
+#[command]
+pub async fn cmd_update_account_preferences(
+    account_id: i64,
+    attrs: UpdatePreferencesAttrs<'_>,
+    state: State<'_, AppState>,
+) -> Result<(), Error> {
+    info!(
+        "Updating preferences of account {}: {:?}",
+        account_id, attrs
+    );
+    async_cmd!(preferences::update(
+        account_id,
+        preferences::UpdatePreferencesAttrs {
+            muted: attrs.muted,
+            sound: attrs.sound,
+            preview: attrs.preview,
+            sound_path: attrs.sound_path.filter(|p| !p.trim().is_empty()),
+        },
+        &state.pool,
+    ))
+    .await
+}
@@ -0,0 +1,123 @@
+use log::info;
+use serde::Deserialize;
+use tauri::{command, State};
+
+use crate::{
+    async_cmd,
+    db::rules,
+    error::Error,
+    message::MessageHeaders,
+    models::{MatchType, Rule, RuleAction, RuleField},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct RuleAttrs<'a> {
+    /// Leave empty to apply the rule to every account
+    pub account_id: Option<i64>,
+    pub name: &'a str,
+    pub field: RuleField,
+    pub match_type: MatchType,
+    pub pattern: &'a str,
+    pub action: RuleAction,
+    /// Sound played by `RuleAction::Sound` rules
+    pub sound_path: Option<&'a str>,
+    pub position: i64,
+    pub enabled: bool,
+}
+
+impl<'a> RuleAttrs<'a> {
+    fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::CustomError {
+                message: "rule name is empty".to_string(),
+            });
+        }
+        if self.action == RuleAction::Sound && self.sound_path().is_none() {
+            return Err(Error::CustomError {
+                message: "sound rules need a sound".to_string(),
+            });
+        }
+        Rule::validate(self.match_type, self.pattern).map_err(|e| Error::CustomError {
+            message: e.to_string(),
+        })
+    }
+
+    fn sound_path(&self) -> Option<&'a str> {
+        self.sound_path.filter(|p| !p.trim().is_empty())
+    }
+
+    fn to_db(&self) -> rules::RuleAttrs<'a> {
+        rules::RuleAttrs {
+            account_id: self.account_id,
+            name: self.name.trim(),
+            field: self.field,
+            match_type: self.match_type,
+            pattern: self.pattern,
+            action: self.action,
+            sound_path: self.sound_path(),
+            position: self.position,
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// Command to list the notification rules in evaluation order
+#[command]
+pub async fn cmd_list_rules(state: State<'_, AppState>) -> Result<Vec<Rule>, Error> {
+    async_cmd!(rules::all(&state.pool)).await
+}
+
+/// Command to create a notification rule
+/// Rules are read on every notification, running sessions are not restarted
+#[command]
+pub async fn cmd_create_rule(
+    attrs: RuleAttrs<'_>,
+    state: State<'_, AppState>,
+) -> Result<Rule, Error> {
+    info!("Creating rule: {:?}", attrs);
+    attrs.validate()?;
+    async_cmd!(rules::create(attrs.to_db(), &state.pool)).await
+}
+
+/// Command to update a notification rule
+#[command]
+pub async fn cmd_update_rule(
+    id: i64,
+    attrs: RuleAttrs<'_>,
+    state: State<'_, AppState>,
+) -> Result<(), Error> {
+    info!("Updating rule {}: {:?}", id, attrs);
+    attrs.validate()?;
+    async_cmd!(rules::update(id, attrs.to_db(), &state.pool)).await
+}
+
+/// Command to delete a notification rule
+#[command]
+pub async fn cmd_delete_rule(id: i64, state: State<'_, AppState>) -> Result<(), Error> {
+    async_cmd!(rules::delete(id, &state.pool)).await
+}
+
+/// Command to check a rule against sample headers without saving it
+/// Returns whether the rule matches, invalid patterns are reported as errors
+#[command]
+pub async fn cmd_test_rule(attrs: RuleAttrs<'_>, headers: MessageHeaders) -> Result<bool, Error> {
+    attrs.validate()?;
+
+    let rule = Rule {
+        id: 0,
+        account_id: None,
+        name: attrs.name.to_string(),
+        field: attrs.field,
+        match_type: attrs.match_type,
+        pattern: attrs.pattern.to_string(),
+        action: attrs.action,
+        sound_path: None,
+        position: attrs.position,
+        enabled: true,
+    };
+
+    rule.matches(0, &headers).map_err(|e| Error::CustomError {
+        message: e.to_string(),
+    })
+}
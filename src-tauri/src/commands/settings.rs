@@ -1,14 +1,32 @@
 use log::info;
 use serde::Deserialize;
-use tauri::{command, State, Window};
+use tauri::{command, AppHandle, State, Window};
 
-use crate::{async_cmd, db::settings, error::Error, models::Settings, AppState};
+use crate::{
+    async_cmd,
+    db::settings,
+    error::Error,
+    models::{PauseDuration, Settings},
+    pause, quiet_hours, AppState,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateSettingsAttrs {
     pub notifications: Option<bool>,
     pub sound: Option<bool>,
     pub preview: Option<bool>,
+    pub volume: Option<f64>,
+    pub quiet_hours: Option<bool>,
+    /// `HH:MM`, local time
+    pub quiet_start: Option<String>,
+    /// `HH:MM`, local time
+    pub quiet_end: Option<String>,
+    pub quiet_days: Option<i64>,
+    pub quiet_summary: Option<bool>,
+    /// Seconds, 0 disables coalescing
+    pub coalesce_window: Option<i64>,
+    /// Notifications per minute, 0 disables the limit
+    pub rate_limit: Option<i64>,
 }
 
 #[command]
@@ -18,15 +36,34 @@ pub async fn cmd_update_settings(
     state: State<'_, AppState>,
 ) -> Result<(), Error> {
     info!("Updating settings: {:?}", attrs);
+
+    for time in [&attrs.quiet_start, &attrs.quiet_end].into_iter().flatten() {
+        if let Err(e) = quiet_hours::parse_time(time) {
+            return Err(Error::CustomError {
+                message: e.to_string(),
+            });
+        }
+    }
+
     async_cmd!(settings::update(
         settings::UpdateSettingsAttrs {
             notifications: attrs.notifications,
             sound: attrs.sound,
-            preview: attrs.preview
+            preview: attrs.preview,
+            volume: attrs.volume,
+            quiet_hours: attrs.quiet_hours,
+            quiet_start: attrs.quiet_start,
+            quiet_end: attrs.quiet_end,
+            quiet_days: attrs.quiet_days,
+            quiet_summary: attrs.quiet_summary,
+            coalesce_window: attrs.coalesce_window,
+            rate_limit: attrs.rate_limit,
         },
         &state.pool,
     ))
-    .await
+    .await?;
+
+    Ok(())
 }
 
 #[command]
@@ -36,3 +73,23 @@ pub async fn cmd_fetch_settings(
 ) -> Result<Option<Settings>, Error> {
     async_cmd!(settings::fetch(&state.pool)).await
 }
+
+/// Command to pause notifications for a while
+/// Returns the unix timestamp when notifications resume
+#[command]
+pub async fn cmd_pause_notifications(
+    duration: PauseDuration,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<i64, Error> {
+    async_cmd!(pause::pause(&app, &state.pool, duration)).await
+}
+
+/// Command to resume paused notifications
+#[command]
+pub async fn cmd_resume_notifications(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), Error> {
+    async_cmd!(pause::resume(&app, &state.pool)).await
+}
@@ -0,0 +1,47 @@
+use log::info;
+use serde::Deserialize;
+use tauri::{command, State};
+
+use crate::{async_cmd, db::vip, error::Error, models::VipSender, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateVipSenderAttrs<'a> {
+    /// Leave empty to apply to every account
+    pub account_id: Option<i64>,
+    /// Email address or domain
+    pub address: &'a str,
+}
+
+/// Command to list the VIP senders
+#[command]
+pub async fn cmd_list_vip_senders(state: State<'_, AppState>) -> Result<Vec<VipSender>, Error> {
+    async_cmd!(vip::all(&state.pool)).await
+}
+
+/// Command to add a VIP sender
+/// Mail from VIPs is notified as important, even when paused or during quiet hours
+#[command]
+pub async fn cmd_create_vip_sender(
+    attrs: CreateVipSenderAttrs<'_>,
+    state: State<'_, AppState>,
+) -> Result<VipSender, Error> {
+    info!("Adding VIP sender: {:?}", attrs);
+    let address = VipSender::normalize(attrs.address).map_err(|e| Error::CustomError {
+        message: e.to_string(),
+    })?;
+
+    async_cmd!(vip::create(
+        vip::CreateVipSenderAttrs {
+            account_id: attrs.account_id,
+            address: &address,
+        },
+        &state.pool
+    ))
+    .await
+}
+
+/// Command to remove a VIP sender
+#[command]
+pub async fn cmd_delete_vip_sender(id: i64, state: State<'_, AppState>) -> Result<(), Error> {
+    async_cmd!(vip::delete(id, &state.pool)).await
+}
@@ -2,7 +2,9 @@ use anyhow::Result;
 use serde::Deserialize;
 use sqlx::{query_as, Pool, Sqlite};
 
-use crate::models::Account;
+use crate::models::{Account, AuthMethod, OAuthProviderKind, Security};
+
+use super::mailbox;
 
 #[derive(Debug, Deserialize)]
 pub struct CreateAccountAttrs<'a> {
@@ -13,6 +15,10 @@ pub struct CreateAccountAttrs<'a> {
     pub active: bool,
     pub username: &'a str,
     pub mailbox: &'a str,
+    pub security: Security,
+    pub auth_method: AuthMethod,
+    pub oauth_provider: Option<OAuthProviderKind>,
+    pub poll_interval: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,6 +29,10 @@ pub struct UpdateAccountAttrs<'a> {
     pub color: &'a str,
     pub username: &'a str,
     pub mailbox: &'a str,
+    pub security: Security,
+    pub auth_method: AuthMethod,
+    pub oauth_provider: Option<OAuthProviderKind>,
+    pub poll_interval: i64,
 }
 
 /// Creates a new account in the database and returns its unique identifier.
@@ -33,9 +43,10 @@ pub struct UpdateAccountAttrs<'a> {
 pub async fn create(attrs: CreateAccountAttrs<'_>, pool: &Pool<Sqlite>) -> Result<Account> {
     let query = r#"
         INSERT INTO accounts 
-            (name, server, port, color, active, username, mailbox) 
+            (name, server, port, color, active, username, mailbox, security,
+            auth_method, oauth_provider, poll_interval) 
         VALUES 
-            ($1,$2,$3,$4,$5,$6,$7)
+            ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11)
     "#;
 
     let id = sqlx::query(query)
@@ -46,6 +57,10 @@ pub async fn create(attrs: CreateAccountAttrs<'_>, pool: &Pool<Sqlite>) -> Resul
         .bind(attrs.active)
         .bind(attrs.username)
         .bind(attrs.mailbox)
+        .bind(attrs.security)
+        .bind(attrs.auth_method)
+        .bind(attrs.oauth_provider)
+        .bind(attrs.poll_interval)
         .execute(pool)
         .await?
         .last_insert_rowid();
@@ -65,6 +80,20 @@ pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<()> {
     Ok(())
 }
 
+/// Enable or disable an account, disabled accounts are not watched
+///
+/// # Arguments
+/// * `id` - account id to be updated
+/// * `active` - whether the account should be watched
+pub async fn set_active(id: i64, active: bool, pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(r#"UPDATE accounts SET active = $1 WHERE id = $2"#)
+        .bind(active)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 /// List all accounts ordered by id in descending order
 ///
 /// # Arguments
@@ -72,14 +101,21 @@ pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<()> {
 pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Account>> {
     let result = sqlx::query_as::<_, Account>(
         r#"
-        SELECT id, name, color, server, port, active, username, mailbox
+        SELECT id, name, color, server, port, active, username, mailbox, security,
+            auth_method, oauth_provider, poll_interval
         FROM accounts 
         ORDER BY id desc
     "#,
     )
     .fetch_all(pool)
     .await?;
-    Ok(result)
+
+    let mut accounts = Vec::with_capacity(result.len());
+    for acc in result {
+        accounts.push(with_mailboxes(acc, pool).await?);
+    }
+
+    Ok(accounts)
 }
 
 /// Find an account by id
@@ -90,7 +126,8 @@ pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Account>> {
 pub async fn find(id: i64, pool: &Pool<Sqlite>) -> Result<Account> {
     let result = query_as::<_, Account>(
         r#"
-        SELECT id, name, color, server, port, active, username, mailbox
+        SELECT id, name, color, server, port, active, username, mailbox, security,
+            auth_method, oauth_provider, poll_interval
         FROM accounts 
         WHERE id = ?
     "#,
@@ -99,15 +136,26 @@ pub async fn find(id: i64, pool: &Pool<Sqlite>) -> Result<Account> {
     .fetch_one(pool)
     .await?;
 
-    Ok(result)
+    with_mailboxes(result, pool).await
+}
+
+/// Load the watched mailboxes of an account
+/// Accounts without any entry fall back to their main mailbox
+async fn with_mailboxes(mut acc: Account, pool: &Pool<Sqlite>) -> Result<Account> {
+    acc.mailboxes = mailbox::all(acc.id, pool).await?;
+    if acc.mailboxes.is_empty() {
+        acc.mailboxes.push(acc.mailbox.clone());
+    }
+    Ok(acc)
 }
 
 pub async fn update(id: i64, attrs: UpdateAccountAttrs<'_>, pool: &Pool<Sqlite>) -> Result<()> {
     sqlx::query(
         r#"
         UPDATE accounts  
-        SET name = $1, color = $2, server = $3, port = $4, username = $5, mailbox = $6
-        WHERE id = $7
+        SET name = $1, color = $2, server = $3, port = $4, username = $5, mailbox = $6, security = $7,
+            auth_method = $8, oauth_provider = $9, poll_interval = $10
+        WHERE id = $11
     "#,
     )
     .bind(attrs.name)
@@ -116,6 +164,10 @@ pub async fn update(id: i64, attrs: UpdateAccountAttrs<'_>, pool: &Pool<Sqlite>)
     .bind(attrs.port)
     .bind(attrs.username)
     .bind(attrs.mailbox)
+    .bind(attrs.security)
+    .bind(attrs.auth_method)
+    .bind(attrs.oauth_provider)
+    .bind(attrs.poll_interval)
     .bind(id)
     .execute(pool)
     .await?;
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use sqlx::{query_as, Pool, Sqlite};
+
+use crate::models::MailboxState;
+
+/// List the mailboxes watched for an account
+///
+/// # Arguments
+/// * `account_id` - The account id
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn all(account_id: i64, pool: &Pool<Sqlite>) -> Result<Vec<String>> {
+    let result = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT name
+        FROM account_mailboxes
+        WHERE account_id = $1
+        ORDER BY id asc
+    "#,
+    )
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(result)
+}
+
+/// Replace the list of watched mailboxes of an account
+///
+/// # Arguments
+/// * `account_id` - The account id
+/// * `names` - Mailbox names to be watched
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn replace(account_id: i64, names: &[&str], pool: &Pool<Sqlite>) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(r#"DELETE FROM account_mailboxes WHERE account_id = $1"#)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for name in names {
+        sqlx::query(
+            r#"INSERT OR IGNORE INTO account_mailboxes (account_id, name) VALUES ($1, $2)"#,
+        )
+        .bind(account_id)
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// List the last notified state of every watched mailbox
+///
+/// # Arguments
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn states(pool: &Pool<Sqlite>) -> Result<Vec<MailboxState>> {
+    let result = query_as::<_, MailboxState>(
+        r#"
+        SELECT account_id, mailbox, uid_validity, last_uid
+        FROM mailbox_states
+    "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(result)
+}
+
+/// Create or update the last notified state of a mailbox
+/// A state older than the saved one is ignored, unless UIDVALIDITY changed
+///
+/// # Arguments
+/// * `state` - The mailbox state to be saved
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn save_state(state: &MailboxState, pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO mailbox_states (account_id, mailbox, uid_validity, last_uid)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (account_id, mailbox)
+        DO UPDATE SET uid_validity = excluded.uid_validity, last_uid = excluded.last_uid
+        WHERE excluded.uid_validity != uid_validity OR excluded.last_uid > last_uid
+    "#,
+    )
+    .bind(state.account_id)
+    .bind(&state.mailbox)
+    .bind(state.uid_validity)
+    .bind(state.last_uid)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -1,5 +1,9 @@
 pub mod account;
+pub mod mailbox;
+pub mod preferences;
+pub mod rules;
 pub mod settings;
+pub mod vip;
 
 use sqlx::{Pool, Sqlite, SqlitePool};
 use std::path::Path;
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use serde::Deserialize;
+use sqlx::{query_as, Pool, Sqlite};
+
+use crate::models::AccountPreferences;
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesAttrs<'a> {
+    pub muted: Option<bool>,
+    pub sound: Option<bool>,
+    pub preview: Option<bool>,
+    pub sound_path: Option<&'a str>,
+}
+
+/// Find the notification preferences of an account
+/// Accounts without overrides get empty preferences
+///
+/// # Arguments
+/// * `account_id` - The account id
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn find(account_id: i64, pool: &Pool<Sqlite>) -> Result<AccountPreferences> {
+    let result = query_as::<_, AccountPreferences>(
+        r#"
+        SELECT account_id, muted, sound, preview, sound_path
+        FROM account_preferences
+        WHERE account_id = $1
+    "#,
+    )
+    .bind(account_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.unwrap_or(AccountPreferences {
+        account_id,
+        ..Default::default()
+    }))
+}
+
+/// Create or replace the notification preferences of an account
+///
+/// # Arguments
+/// * `account_id` - The account id
+/// * `attrs` - `UpdatePreferencesAttrs`, `None` values use the global settings
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn update(
+    account_id: i64,
+    attrs: UpdatePreferencesAttrs<'_>,
+    pool: &Pool<Sqlite>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO account_preferences (account_id, muted, sound, preview, sound_path)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (account_id)
+        DO UPDATE SET muted = excluded.muted, sound = excluded.sound,
+            preview = excluded.preview, sound_path = excluded.sound_path
+    "#,
+    )
+    .bind(account_id)
+    .bind(attrs.muted)
+    .bind(attrs.sound)
+    .bind(attrs.preview)
+    .bind(attrs.sound_path)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
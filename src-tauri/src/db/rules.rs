@@ -0,0 +1,133 @@
+use anyhow::Result;
+use serde::Deserialize;
+use sqlx::{query_as, Pool, Sqlite};
+
+use crate::models::{MatchType, Rule, RuleAction, RuleField};
+
+#[derive(Debug, Deserialize)]
+pub struct RuleAttrs<'a> {
+    pub account_id: Option<i64>,
+    pub name: &'a str,
+    pub field: RuleField,
+    pub match_type: MatchType,
+    pub pattern: &'a str,
+    pub action: RuleAction,
+    pub sound_path: Option<&'a str>,
+    pub position: i64,
+    pub enabled: bool,
+}
+
+/// List every rule in evaluation order
+///
+/// # Arguments
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<Rule>> {
+    let rules = query_as::<_, Rule>(
+        r#"
+        SELECT id, account_id, name, field, match_type, pattern, action,
+            sound_path, position, enabled
+        FROM rules
+        ORDER BY position ASC, id ASC
+    "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rules)
+}
+
+/// Find a rule by id
+///
+/// # Arguments
+/// * `id` - The rule id
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn find(id: i64, pool: &Pool<Sqlite>) -> Result<Rule> {
+    let rule = query_as::<_, Rule>(
+        r#"
+        SELECT id, account_id, name, field, match_type, pattern, action,
+            sound_path, position, enabled
+        FROM rules
+        WHERE id = $1
+    "#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+/// Create a rule
+///
+/// # Arguments
+/// * `attrs` - `RuleAttrs` rule attributes
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn create(attrs: RuleAttrs<'_>, pool: &Pool<Sqlite>) -> Result<Rule> {
+    let id = sqlx::query(
+        r#"
+        INSERT INTO rules
+            (account_id, name, field, match_type, pattern, action,
+            sound_path, position, enabled)
+        VALUES
+            ($1,$2,$3,$4,$5,$6,$7,$8,$9)
+    "#,
+    )
+    .bind(attrs.account_id)
+    .bind(attrs.name)
+    .bind(attrs.field)
+    .bind(attrs.match_type)
+    .bind(attrs.pattern)
+    .bind(attrs.action)
+    .bind(attrs.sound_path)
+    .bind(attrs.position)
+    .bind(attrs.enabled)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    find(id, pool).await
+}
+
+/// Update a rule
+///
+/// # Arguments
+/// * `id` - The rule id
+/// * `attrs` - `RuleAttrs` rule attributes
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn update(id: i64, attrs: RuleAttrs<'_>, pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE rules
+        SET account_id = $1, name = $2, field = $3, match_type = $4, pattern = $5,
+            action = $6, sound_path = $7, position = $8, enabled = $9
+        WHERE id = $10
+    "#,
+    )
+    .bind(attrs.account_id)
+    .bind(attrs.name)
+    .bind(attrs.field)
+    .bind(attrs.match_type)
+    .bind(attrs.pattern)
+    .bind(attrs.action)
+    .bind(attrs.sound_path)
+    .bind(attrs.position)
+    .bind(attrs.enabled)
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete a rule
+///
+/// # Arguments
+/// * `id` - The rule id
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(r#"DELETE FROM rules WHERE id = $1"#)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
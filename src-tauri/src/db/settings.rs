@@ -10,6 +10,14 @@ pub struct UpdateSettingsAttrs {
     pub notifications: Option<bool>,
     pub sound: Option<bool>,
     pub preview: Option<bool>,
+    pub volume: Option<f64>,
+    pub quiet_hours: Option<bool>,
+    pub quiet_start: Option<String>,
+    pub quiet_end: Option<String>,
+    pub quiet_days: Option<i64>,
+    pub quiet_summary: Option<bool>,
+    pub coalesce_window: Option<i64>,
+    pub rate_limit: Option<i64>,
 }
 
 pub async fn fetch(pool: &Pool<Sqlite>) -> Result<Option<Settings>> {
@@ -27,11 +35,65 @@ pub async fn fetch(pool: &Pool<Sqlite>) -> Result<Option<Settings>> {
 
 pub async fn update(attrs: UpdateSettingsAttrs, pool: &Pool<Sqlite>) -> Result<()> {
     debug!("Update settings: {:?}", attrs);
-    sqlx::query(r#"UPDATE settings set notifications = $1, preview = $2, sound = $3"#)
-        .bind(attrs.notifications.unwrap_or(false))
-        .bind(attrs.preview.unwrap_or(false))
-        .bind(attrs.sound.unwrap_or(false))
+    sqlx::query(
+        r#"
+        UPDATE settings set notifications = $1, preview = $2, sound = $3, volume = COALESCE($4, volume),
+            quiet_hours = $5, quiet_start = COALESCE($6, quiet_start),
+            quiet_end = COALESCE($7, quiet_end), quiet_days = COALESCE($8, quiet_days),
+            quiet_summary = COALESCE($9, quiet_summary),
+            coalesce_window = COALESCE($10, coalesce_window),
+            rate_limit = COALESCE($11, rate_limit)
+    "#,
+    )
+    .bind(attrs.notifications.unwrap_or(false))
+    .bind(attrs.preview.unwrap_or(false))
+    .bind(attrs.sound.unwrap_or(false))
+    .bind(attrs.volume.map(|v| v.clamp(0.0, 1.0)))
+    .bind(attrs.quiet_hours.unwrap_or(false))
+    .bind(attrs.quiet_start)
+    .bind(attrs.quiet_end)
+    .bind(attrs.quiet_days)
+    .bind(attrs.quiet_summary)
+    .bind(attrs.coalesce_window.map(|w| w.max(0)))
+    .bind(attrs.rate_limit.map(|l| l.max(0)))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Pause notifications until the given unix timestamp, `None` resumes them
+pub async fn set_paused_until(paused_until: Option<i64>, pool: &Pool<Sqlite>) -> Result<()> {
+    debug!("Pause notifications until: {:?}", paused_until);
+    sqlx::query(r#"UPDATE settings set paused_until = $1"#)
+        .bind(paused_until)
         .execute(pool)
         .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::{db::run_migrations, models::AccountPreferences};
+
+    #[tokio::test]
+    async fn seeded_settings_notify() {
+        // A single connection, every connection to an in-memory database opens a new one
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        let settings = fetch(&pool).await.unwrap().unwrap();
+        let prefs = AccountPreferences::default().resolve(&settings);
+
+        assert!(prefs.enabled);
+        assert!(!prefs.sound);
+        assert!(!prefs.preview);
+        assert_eq!(prefs.volume, 1.0);
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::Result;
+use serde::Deserialize;
+use sqlx::{query_as, Pool, Sqlite};
+
+use crate::models::VipSender;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateVipSenderAttrs<'a> {
+    pub account_id: Option<i64>,
+    pub address: &'a str,
+}
+
+/// List every VIP sender
+///
+/// # Arguments
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn all(pool: &Pool<Sqlite>) -> Result<Vec<VipSender>> {
+    let senders = query_as::<_, VipSender>(
+        r#"SELECT id, account_id, address FROM vip_senders ORDER BY address ASC"#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(senders)
+}
+
+/// Add a VIP sender
+///
+/// # Arguments
+/// * `attrs` - `CreateVipSenderAttrs`, the address must be normalized
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn create(attrs: CreateVipSenderAttrs<'_>, pool: &Pool<Sqlite>) -> Result<VipSender> {
+    let id = sqlx::query(r#"INSERT INTO vip_senders (account_id, address) VALUES ($1, $2)"#)
+        .bind(attrs.account_id)
+        .bind(attrs.address)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+
+    let sender = query_as::<_, VipSender>(
+        r#"SELECT id, account_id, address FROM vip_senders WHERE id = $1"#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(sender)
+}
+
+/// Remove a VIP sender
+///
+/// # Arguments
+/// * `id` - The VIP sender id
+/// * `pool` - A reference to the SQLite connection pool.
+pub async fn delete(id: i64, pool: &Pool<Sqlite>) -> Result<()> {
+    sqlx::query(r#"DELETE FROM vip_senders WHERE id = $1"#)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -12,3 +12,21 @@ impl serde::Serialize for Error {
         serializer.serialize_str(self.to_string().as_ref())
     }
 }
+
+/// IMAP failures that reconnecting will not fix
+/// The watcher stops retrying when one of these errors is returned
+#[derive(thiserror::Error, Debug)]
+pub enum ConnectionError {
+    #[error("authentication failed: {0}")]
+    AuthFailed(String),
+    #[error("credentials not found in the keychain")]
+    MissingCredentials,
+    #[error("mailbox not found: {0}")]
+    MailboxNotFound(String),
+}
+
+/// TLS handshake failures, e.g. an untrusted or expired certificate
+/// The watcher keeps retrying, but reports them apart from network errors
+#[derive(thiserror::Error, Debug)]
+#[error("TLS error: {0}")]
+pub struct TlsError(pub String);
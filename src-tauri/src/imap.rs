@@ -1,15 +1,175 @@
-use std::{cmp, collections::HashMap, net::TcpStream, sync::Mutex};
+use std::{
+    cmp,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::{keychain::Keychain, models::Account, ChannelCmd, UnboundedChannel};
+use crate::{
+    certificate,
+    error::{ConnectionError, TlsError},
+    keychain::Keychain,
+    message::MessageHeaders,
+    models::{
+        Account, AuthMethod, ConnectionReport, ConnectionState, MailboxState, Security,
+        StatusUpdate, TestStage, TestStep, WatchMode,
+    },
+    oauth::{self, OAuthProvider},
+    watcher::Shutdown,
+    ChannelCmd, UnboundedChannel,
+};
 use anyhow::{anyhow, Result};
 use flume::Sender;
-use imap::Session;
-use lazy_static::lazy_static;
-use log::{error, info};
+use imap::{extensions::idle::SetReadTimeout, types::Capabilities, Authenticator, Client, Session};
+use imap_proto::types::Capability;
+use log::{error, info, warn};
 use native_tls::{TlsConnector, TlsStream};
 
-lazy_static! {
-    static ref LAST_NOTIFIED: Mutex<HashMap<i64, u32>> = Mutex::new(HashMap::new());
+/// Polling intervals shorter than this are not honored, to avoid hammering the server
+pub const MIN_POLL_INTERVAL: u64 = 30;
+
+/// IDLE is re-issued after this delay, servers may log out clients idling for 30 minutes
+/// https://datatracker.ietf.org/doc/html/rfc2177#section-3
+const IDLE_KEEPALIVE: Duration = Duration::from_secs(29 * 60);
+
+/// How often blocking reads check for a shutdown request
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time left to the server to answer DONE and LOGOUT once a shutdown is requested
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Give up on addresses that do not accept the connection in time,
+/// firewalls often drop packets instead of refusing the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Longest wait for the server to answer, IDLE uses `IDLE_KEEPALIVE` instead
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Plain or encrypted connection to the server
+#[derive(Debug)]
+enum Transport {
+    Tls(TlsStream<TcpStream>),
+    Plain(TcpStream),
+}
+
+impl Transport {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Transport::Tls(s) => s.get_ref(),
+            Transport::Plain(s) => s,
+        }
+    }
+}
+
+/// Underlying IMAP transport
+///
+/// Sessions are always built on top of this stream, no matter which
+/// `Security` mode the account uses, so the rest of the code only
+/// has to deal with a single `Session<ImapStream>` type.
+///
+/// Streams of watcher sessions carry a `Shutdown` signal: reads are done in short
+/// slices so a pending IDLE is interrupted (as if it timed out) once shutdown is requested.
+#[derive(Debug)]
+pub struct ImapStream {
+    transport: Transport,
+    shutdown: Option<Shutdown>,
+    /// Read timeout requested by the `imap` crate, `READ_TIMEOUT` by default
+    read_timeout: Option<Duration>,
+    /// Whether a read was already interrupted by the shutdown signal
+    interrupted: bool,
+}
+
+impl ImapStream {
+    fn new(transport: Transport, shutdown: Option<Shutdown>) -> Result<Self> {
+        if shutdown.is_some() {
+            transport
+                .tcp()
+                .set_read_timeout(Some(SHUTDOWN_CHECK_INTERVAL))?;
+        }
+
+        Ok(Self {
+            transport,
+            shutdown,
+            read_timeout: Some(READ_TIMEOUT),
+            interrupted: false,
+        })
+    }
+
+    fn read_once(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.transport {
+            Transport::Tls(s) => s.read(buf),
+            Transport::Plain(s) => s.read(buf),
+        }
+    }
+}
+
+impl Read for ImapStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(shutdown) = self.shutdown.clone() else {
+            return self.read_once(buf);
+        };
+
+        let started_at = Instant::now();
+        loop {
+            match self.read_once(buf) {
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    let elapsed = started_at.elapsed();
+                    if shutdown.is_requested() {
+                        // The first interruption ends IDLE, later reads get a grace period
+                        // so DONE and LOGOUT can still be answered
+                        if !self.interrupted {
+                            self.interrupted = true;
+                            return Err(e);
+                        }
+                        if elapsed >= SHUTDOWN_GRACE {
+                            return Err(e);
+                        }
+                    }
+                    if self.read_timeout.is_some_and(|timeout| elapsed >= timeout) {
+                        return Err(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Write for ImapStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.transport {
+            Transport::Tls(s) => s.write(buf),
+            Transport::Plain(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.transport {
+            Transport::Tls(s) => s.flush(),
+            Transport::Plain(s) => s.flush(),
+        }
+    }
+}
+
+impl SetReadTimeout for ImapStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> imap::error::Result<()> {
+        // IDLE removes its timeout once done, fall back to the default one
+        let timeout = timeout.or(Some(READ_TIMEOUT));
+        self.read_timeout = timeout;
+
+        // Keep reading in short slices while a shutdown signal is attached
+        let timeout = match self.shutdown {
+            Some(_) => {
+                Some(timeout.map_or(SHUTDOWN_CHECK_INTERVAL, |t| t.min(SHUTDOWN_CHECK_INTERVAL)))
+            }
+            None => timeout,
+        };
+        self.transport
+            .tcp()
+            .set_read_timeout(timeout)
+            .map_err(imap::error::Error::Io)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,75 +178,213 @@ pub struct ConnectionDetails<'a> {
     pub server: (&'a str, i64),
     /// Server username
     pub username: &'a str,
-    /// Server password
+    /// Server password, or the access token for OAuth2 accounts
     pub password: &'a str,
     /// Mailbox name
     pub mailbox: &'a str,
+    /// Transport security
+    pub security: Security,
+    /// Authentication mechanism
+    pub auth_method: AuthMethod,
+}
+
+/// SASL XOAUTH2 and OAUTHBEARER authenticator
+/// https://developers.google.com/gmail/imap/xoauth2-protocol
+/// https://datatracker.ietf.org/doc/html/rfc7628
+struct OAuth2<'a> {
+    method: AuthMethod,
+    user: &'a str,
+    access_token: &'a str,
+}
+
+impl<'a> Authenticator for OAuth2<'a> {
+    type Response = String;
+
+    fn process(&self, challenge: &[u8]) -> Self::Response {
+        // A non empty challenge is the server error details,
+        // an empty response is expected to finish the exchange
+        if !challenge.is_empty() {
+            return String::new();
+        }
+
+        match self.method {
+            // The authzid of the gs2 header is a saslname, `=` and `,` are escaped
+            // https://datatracker.ietf.org/doc/html/rfc5801#section-4
+            AuthMethod::OAuthBearer => format!(
+                "n,a={},\x01auth=Bearer {}\x01\x01",
+                self.user.replace('=', "=3D").replace(',', "=2C"),
+                self.access_token
+            ),
+            _ => format!(
+                "user={}\x01auth=Bearer {}\x01\x01",
+                self.user, self.access_token
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Imap<'ac> {
     /// Account information
     account: Option<&'ac Account>,
+    /// UIDVALIDITY of the mailbox selected by the last `connect`
+    uid_validity: Option<u32>,
+    /// Signal used by the watcher to stop the sessions opened by this client
+    shutdown: Option<Shutdown>,
 }
 
 impl<'ac> Imap<'ac> {
     pub fn new(account: Option<&'ac Account>) -> Self {
-        Self { account }
+        Self {
+            account,
+            uid_validity: None,
+            shutdown: None,
+        }
+    }
+
+    /// Stop sessions opened by this client when `shutdown` is requested
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
     }
 
-    /// connect will initialize a new `Client<TlsStream<TcpStream>>` client
-    /// login and verify server capabilities (IDLE support)
-    pub fn connect_account(&mut self) -> Result<Session<TlsStream<TcpStream>>> {
+    /// connect will initialize a new `Client<ImapStream>` client
+    /// login and select the given mailbox of the account
+    pub fn connect_account(&mut self, mailbox: &str) -> Result<Session<ImapStream>> {
         if self.account.is_none() {
             return Err(anyhow!("Invalid account"));
         }
 
         let acc = self.account.unwrap();
 
-        let password = Keychain::new(acc.id, &acc.username)
-            .get_password()
-            .expect("Password not found");
+        let keychain = Keychain::new(acc.id, &acc.username);
+        let password = match (acc.auth_method, acc.oauth_provider) {
+            (AuthMethod::Password, _) => keychain
+                .get_password()
+                .map_err(|_| ConnectionError::MissingCredentials)?,
+            (_, Some(provider)) => {
+                oauth::access_token(&OAuthProvider::from_kind(provider), &keychain)?
+            }
+            (_, None) => return Err(anyhow!("OAuth provider not configured")),
+        };
 
         self.connect(&ConnectionDetails {
             server: (&acc.server, acc.port),
             username: &acc.username,
             password: &password,
-            mailbox: &acc.mailbox,
+            mailbox,
+            security: acc.security,
+            auth_method: acc.auth_method,
         })
     }
 
-    pub fn connect(&mut self, conn: &ConnectionDetails) -> Result<Session<TlsStream<TcpStream>>> {
-        let ssl_connector = TlsConnector::builder().build()?;
-        let client = imap::connect(
-            format!("{}:{}", conn.server.0, conn.server.1),
-            conn.server.0,
-            &ssl_connector,
-        )?;
-        match client.login(conn.username, conn.password) {
-            Ok(mut session) => {
-                // Check if the server has IDLE capability
-                // IDLE capability can be used to receive notifications of new messages without polling.
-                // https://datatracker.ietf.org/doc/html/rfc2177
-                let support_idle = session.capabilities()?.has_str("IDLE");
-                if !support_idle {
-                    let _ = session.logout();
-                    return Err(anyhow!("server does not support IDLE "));
-                }
-                let _ = session.select(conn.mailbox);
+    pub fn connect(&mut self, conn: &ConnectionDetails) -> Result<Session<ImapStream>> {
+        let client = Imap::open(conn, self.shutdown.clone())?;
+        let mut session = Imap::login(client, conn)?;
+        match session.select(conn.mailbox) {
+            Ok(mailbox) => {
+                self.uid_validity = mailbox.uid_validity;
                 Ok(session)
             }
+            Err(imap::error::Error::No(_)) => {
+                let _ = session.logout();
+                Err(ConnectionError::MailboxNotFound(conn.mailbox.to_string()).into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Authenticate with the password or the OAuth2 access token
+    fn login(client: Client<ImapStream>, conn: &ConnectionDetails) -> Result<Session<ImapStream>> {
+        let result = match conn.auth_method {
+            AuthMethod::Password => client.login(conn.username, conn.password),
+            AuthMethod::XOAuth2 | AuthMethod::OAuthBearer => {
+                let mechanism = match conn.auth_method {
+                    AuthMethod::OAuthBearer => "OAUTHBEARER",
+                    _ => "XOAUTH2",
+                };
+                client.authenticate(
+                    mechanism,
+                    &OAuth2 {
+                        method: conn.auth_method,
+                        user: conn.username,
+                        access_token: conn.password,
+                    },
+                )
+            }
+        };
+        match result {
+            Ok(session) => Ok(session),
+            // The server answered, but rejected the credentials
+            Err((imap::error::Error::No(msg), _)) | Err((imap::error::Error::Bad(msg), _)) => {
+                Err(ConnectionError::AuthFailed(msg).into())
+            }
             Err((e, _)) => Err(anyhow!(e.to_string())),
         }
     }
 
+    /// Check if the server has IDLE capability
+    /// IDLE capability can be used to receive notifications of new messages without polling.
+    /// Servers without it are checked periodically instead.
+    /// https://datatracker.ietf.org/doc/html/rfc2177
+    pub fn watch_mode(capabilities: &Capabilities) -> WatchMode {
+        match capabilities.has_str("IDLE") {
+            true => WatchMode::Idle,
+            false => WatchMode::Polling,
+        }
+    }
+
+    /// Open the transport to the server according to the `Security` mode
+    /// and read the server greeting.
+    ///
+    /// For STARTTLS the greeting is read on the plain connection, before the upgrade.
+    /// https://datatracker.ietf.org/doc/html/rfc2595
+    fn open(conn: &ConnectionDetails, shutdown: Option<Shutdown>) -> Result<Client<ImapStream>> {
+        let (host, port) = conn.server;
+        let addrs = resolve(host, port)?;
+
+        let client = match conn.security {
+            Security::Tls => {
+                let tcp = connect_tcp(&addrs)?;
+                let tls = tls_connect(host, tcp)?;
+                let mut client = Client::new(ImapStream::new(Transport::Tls(tls), shutdown)?);
+                client.read_greeting()?;
+                client
+            }
+            Security::StartTls => {
+                let tcp = connect_tcp(&addrs)?;
+                starttls(&tcp)?;
+                let tls = tls_connect(host, tcp)?;
+                Client::new(ImapStream::new(Transport::Tls(tls), shutdown)?)
+            }
+            Security::None => {
+                if !is_localhost(host) {
+                    return Err(anyhow!(
+                        "unencrypted connections are only allowed to localhost"
+                    ));
+                }
+                let tcp = connect_tcp(&addrs)?;
+                let mut client = Client::new(ImapStream::new(Transport::Plain(tcp), shutdown)?);
+                client.read_greeting()?;
+                client
+            }
+        };
+
+        Ok(client)
+    }
+
     /// Initialize idle checker
+    /// Falls back to polling (NOOP + UID SEARCH every `poll_interval` seconds)
+    /// when the server does not support IDLE.
     /// For every new message we send a message to the channel
     /// which will be responsible to update the systray icon and show a desktop notification
-    /// Here we also keep track of the last notified message
+    /// Here we also keep track of the last notified message in `state`,
+    /// which is owned by the caller so it survives reconnections
+    /// Returns once the watcher asks for a shutdown, after logging out
     pub fn check_for_new_messages(
         &self,
-        session: &mut Session<TlsStream<TcpStream>>,
+        session: &mut Session<ImapStream>,
+        state: &mut MailboxState,
         tx: &Sender<UnboundedChannel>,
     ) -> Result<()> {
         if self.account.is_none() {
@@ -94,50 +392,717 @@ impl<'ac> Imap<'ac> {
         }
 
         let acc = self.account.unwrap();
+        let mailbox = state.mailbox.clone();
+
+        let capabilities = session.capabilities()?;
+        let mode = Imap::watch_mode(&capabilities);
+        let poll_interval =
+            Duration::from_secs(cmp::max(acc.poll_interval.max(0) as u64, MIN_POLL_INTERVAL));
 
-        info!("Starting watcher for account: {}", acc.username);
+        info!(
+            "Starting watcher for account: {} - {} ({:?})",
+            acc.username, mailbox, mode
+        );
+
+        // Previous uids mean nothing once UIDVALIDITY changes
+        let uid_validity = self.uid_validity.unwrap_or_default() as i64;
+        // (a zero validity means the mailbox was never checked before)
+        if state.uid_validity != uid_validity {
+            if state.uid_validity != 0 {
+                warn!(
+                    "UIDVALIDITY changed for {} - {}, resetting last notified message",
+                    acc.username, mailbox
+                );
+            }
+            state.uid_validity = uid_validity;
+            state.last_uid = 0;
+            if tx
+                .send((ChannelCmd::SaveMailboxState(state.clone()), None))
+                .is_err()
+            {
+                return Imap::logout(session);
+            }
+        }
 
+        let mut unseen: Option<u32> = None;
+        // The state is reported once per session, later checks only refresh `last_checked`
+        let mut reported = false;
         loop {
-            let mut last_notified_history = LAST_NOTIFIED.lock().unwrap();
+            if self.shutdown_requested() {
+                info!("Shutdown requested: {} - {}", acc.username, mailbox);
+                break;
+            }
 
-            info!("Messages checked for account: {:?}", &last_notified_history);
-            if tx.send((ChannelCmd::Notify, Some(acc.clone()))).is_err() {
-                error!("Err while sending message. stopping watcher");
-                break Ok(());
+            let count = Imap::unseen_count(session)?;
+            if unseen != Some(count) {
+                unseen = Some(count);
+                let cmd = ChannelCmd::UnseenCount {
+                    mailbox: mailbox.clone(),
+                    count,
+                };
+                if tx.send((cmd, Some(acc.clone()))).is_err() {
+                    break;
+                }
             }
 
-            info!("Checking account: {}", acc.username);
+            info!(
+                "Checking account: {} - {} (last notified uid {})",
+                acc.username, mailbox, state.last_uid
+            );
+
+            // IDLE also wakes up for flag changes and expunges,
+            // only messages above the last notified uid are new
+            let mut new_uids: Vec<u32> = session
+                .uid_search("NEW 1:*")?
+                .into_iter()
+                .filter(|&uid| uid as i64 > state.last_uid)
+                .collect();
+            new_uids.sort_unstable();
+
+            if let Some(&max_uid) = new_uids.last() {
+                let messages = Imap::fetch_headers(session, &new_uids)?;
+
+                let cmd = ChannelCmd::Notify {
+                    mailbox: mailbox.clone(),
+                    uids: new_uids,
+                    messages,
+                };
+                if tx.send((cmd, Some(acc.clone()))).is_err() {
+                    error!("Err while sending message. stopping watcher");
+                    break;
+                }
 
-            let mut new_uids = session.uid_search("NEW 1:*").expect("new ids");
-            let mut last_notified = match last_notified_history.get(&acc.id) {
-                Some(v) => *v,
-                None => 0,
+                state.last_uid = max_uid as i64;
+                if tx
+                    .send((ChannelCmd::SaveMailboxState(state.clone()), None))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+
+            let cmd = match reported {
+                true => ChannelCmd::Checked {
+                    mailbox: mailbox.clone(),
+                },
+                false => {
+                    let state = match mode {
+                        WatchMode::Idle => ConnectionState::Idle,
+                        WatchMode::Polling => ConnectionState::Polling,
+                    };
+                    ChannelCmd::StatusChanged {
+                        mailbox: mailbox.clone(),
+                        update: StatusUpdate::new(state),
+                    }
+                }
             };
+            reported = true;
+            if tx.send((cmd, Some(acc.clone()))).is_err() {
+                break;
+            }
 
-            if new_uids.iter().all(|&uid| uid <= last_notified) {
-                new_uids.clear();
+            match mode {
+                // Ends on changes, keepalive timeout or shutdown, the loop re-issues IDLE
+                WatchMode::Idle => {
+                    session.idle()?.wait_with_timeout(IDLE_KEEPALIVE)?;
+                }
+                WatchMode::Polling => {
+                    let stopped = match &self.shutdown {
+                        Some(shutdown) => shutdown.sleep(poll_interval),
+                        None => {
+                            thread::sleep(poll_interval);
+                            false
+                        }
+                    };
+                    if !stopped {
+                        session.noop()?;
+                    }
+                }
             }
+        }
+
+        Imap::logout(session)
+    }
 
-            last_notified = cmp::max(last_notified, new_uids.iter().cloned().max().unwrap_or(0));
-            last_notified_history.insert(acc.id, last_notified);
-            session.idle()?.wait_keepalive()?;
+    fn shutdown_requested(&self) -> bool {
+        self.shutdown
+            .as_ref()
+            .is_some_and(|shutdown| shutdown.is_requested())
+    }
+
+    /// Close the session, the server may already be gone so failures are only logged
+    fn logout(session: &mut Session<ImapStream>) -> Result<()> {
+        if let Err(e) = session.logout() {
+            warn!("Failed to logout: {}", e);
         }
+        Ok(())
+    }
+
+    /// Number of unseen messages in the selected mailbox
+    ///
+    /// The server lists the uid of every unseen message, not of every message,
+    /// so the response grows with the unseen count only. Cheaper ways are not usable:
+    /// - `STATUS` "SHOULD NOT be used on the currently selected mailbox", servers
+    ///   may answer it from a stale snapshot or refuse it
+    ///   https://datatracker.ietf.org/doc/html/rfc3501#section-6.3.10
+    /// - `SEARCH RETURN (COUNT)` answers with an ESEARCH response, which the `imap`
+    ///   crate fails to parse, even when the server advertises ESEARCH
+    ///   https://datatracker.ietf.org/doc/html/rfc4731
+    fn unseen_count(session: &mut Session<ImapStream>) -> Result<u32> {
+        Ok(session.uid_search("UNSEEN")?.len() as u32)
     }
 
-    /// Simple connection test
-    /// Validate if imap connection are correct (server, credentials and mailbox)
-    pub fn test_connection(conn_details: &ConnectionDetails) -> Result<String> {
+    /// Fetch the headers used in notifications, ordered by uid
+    pub fn fetch_headers(
+        session: &mut Session<ImapStream>,
+        uids: &[u32],
+    ) -> Result<Vec<MessageHeaders>> {
+        if uids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let uid_set = uids
+            .iter()
+            .map(|uid| uid.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+
+        let fetches = session.uid_fetch(
+            uid_set,
+            "(UID BODY.PEEK[HEADER.FIELDS (FROM TO SUBJECT LIST-ID)])",
+        )?;
+
+        let mut messages: Vec<MessageHeaders> = fetches
+            .iter()
+            .filter_map(|f| Some(MessageHeaders::parse(f.uid?, f.header()?)))
+            .collect();
+        messages.sort_by_key(|m| m.uid);
+
+        Ok(messages)
+    }
+
+    /// Connection test, stage by stage
+    /// Validate if imap connection are correct (server, credentials and mailbox),
+    /// report how the account will be watched (IDLE or polling)
+    /// and which stage failed otherwise
+    /// The test stops early once `shutdown` is requested
+    pub fn test_connection(conn: &ConnectionDetails, shutdown: Shutdown) -> ConnectionReport {
         info!("Testing connection");
-        match Imap::new(None).connect(conn_details) {
-            Ok(mut session) => {
-                info!("test_connection - Connection successful");
-                session.logout()?;
-                Ok("OK".to_string())
+        let mut stages = Stages::new(shutdown);
+        match Imap::run_stages(conn, &mut stages) {
+            Some(()) => info!("test_connection - Connection successful"),
+            None => error!("test_connection - Error: {:?}", stages.report.failed_step()),
+        }
+        stages.report
+    }
+
+    /// Run the stages of a connection test, stops at the first failure
+    fn run_stages(conn: &ConnectionDetails, stages: &mut Stages) -> Option<()> {
+        let (host, port) = conn.server;
+
+        let addrs = stages.run(
+            TestStage::Dns,
+            || resolve(host, port),
+            |addrs| {
+                let ips: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+                format!("{} resolved to {}", host, ips.join(", "))
+            },
+        )?;
+
+        let (tcp, peer) = stages.run(
+            TestStage::Tcp,
+            || {
+                if conn.security == Security::None && !is_localhost(host) {
+                    return Err(anyhow!(
+                        "unencrypted connections are only allowed to localhost"
+                    ));
+                }
+                let tcp = connect_tcp(&addrs)?;
+                let peer = tcp.peer_addr()?;
+                Ok((tcp, peer))
+            },
+            |(_, peer)| format!("Connected to {}", peer),
+        )?;
+
+        let shutdown = stages.shutdown();
+        let greeting = |(_, g): &(Client<ImapStream>, String)| g.trim_end().to_string();
+        let client = match conn.security {
+            Security::Tls => {
+                let tls = stages.tls(host, tcp, peer, |_| Ok(()))?;
+                let (client, _) = stages.run(
+                    TestStage::Greeting,
+                    || read_greeting(Transport::Tls(tls), shutdown),
+                    greeting,
+                )?;
+                client
+            }
+            Security::StartTls => {
+                stages.run(
+                    TestStage::Greeting,
+                    || starttls(&tcp),
+                    |g| g.trim_end().to_string(),
+                )?;
+                let tls = stages.tls(host, tcp, peer, |tcp| starttls(tcp).map(|_| ()))?;
+                let stream = ImapStream::new(Transport::Tls(tls), shutdown);
+                Client::new(stages.check(TestStage::Tls, stream)?)
+            }
+            Security::None => {
+                let (client, _) = stages.run(
+                    TestStage::Greeting,
+                    || read_greeting(Transport::Plain(tcp), shutdown),
+                    greeting,
+                )?;
+                client
+            }
+        };
+
+        let mut session = stages.run(
+            TestStage::Authentication,
+            || Imap::login(client, conn),
+            |_| format!("Authenticated as {}", conn.username),
+        )?;
+
+        let (names, watch_mode) = stages.run(
+            TestStage::Capabilities,
+            || {
+                let capabilities = session.capabilities()?;
+                Ok((
+                    capability_names(&capabilities),
+                    Imap::watch_mode(&capabilities),
+                ))
+            },
+            |(names, _)| names.join(" "),
+        )?;
+        stages.report.watch_mode = Some(watch_mode);
+        stages.report.capabilities = names;
+
+        let (messages, unseen) = stages.run(
+            TestStage::Select,
+            || {
+                let mailbox = match session.select(conn.mailbox) {
+                    Err(imap::error::Error::No(_)) => {
+                        let mailbox = conn.mailbox.to_string();
+                        return Err(ConnectionError::MailboxNotFound(mailbox).into());
+                    }
+                    result => result?,
+                };
+                Ok((mailbox.exists, Imap::unseen_count(&mut session)?))
+            },
+            |(messages, unseen)| {
+                format!(
+                    "{} has {} messages, {} unseen",
+                    conn.mailbox, messages, unseen
+                )
+            },
+        )?;
+        stages.report.messages = Some(messages);
+        stages.report.unseen = Some(unseen);
+
+        let _ = session.logout();
+        Some(())
+    }
+}
+
+/// Records the outcome of the stages of a connection test
+#[derive(Debug)]
+struct Stages {
+    report: ConnectionReport,
+    shutdown: Shutdown,
+}
+
+impl Stages {
+    fn new(shutdown: Shutdown) -> Self {
+        Self {
+            report: ConnectionReport::default(),
+            shutdown,
+        }
+    }
+
+    /// Signal attached to the streams, so a cancelled test stops waiting for the server
+    fn shutdown(&self) -> Option<Shutdown> {
+        Some(self.shutdown.clone())
+    }
+
+    /// Run a stage, `detail` describes its result when it succeeds
+    /// Stages are skipped once the test is cancelled
+    fn run<T>(
+        &mut self,
+        stage: TestStage,
+        f: impl FnOnce() -> Result<T>,
+        detail: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        if self.shutdown.is_requested() {
+            return None;
+        }
+
+        let started_at = Instant::now();
+        let result = f();
+        let elapsed_ms = started_at.elapsed().as_millis() as u64;
+
+        let (ok, detail) = match &result {
+            Ok(value) => (true, detail(value)),
+            Err(e) => (false, e.to_string()),
+        };
+        self.report.steps.push(TestStep {
+            stage,
+            ok,
+            detail,
+            elapsed_ms,
+        });
+        result.ok()
+    }
+
+    /// Record the failure of a step done outside of `run` as a failed `stage`
+    fn check<T>(&mut self, stage: TestStage, result: Result<T>) -> Option<T> {
+        if let Err(e) = &result {
+            self.report.steps.push(TestStep {
+                stage,
+                ok: false,
+                detail: e.to_string(),
+                elapsed_ms: 0,
+            });
+        }
+        result.ok()
+    }
+
+    /// TLS handshake stage, records the certificate of the server
+    /// even when it is rejected
+    fn tls(
+        &mut self,
+        host: &str,
+        tcp: TcpStream,
+        peer: SocketAddr,
+        before_handshake: impl FnOnce(&TcpStream) -> Result<()>,
+    ) -> Option<TlsStream<TcpStream>> {
+        let tls = self.run(
+            TestStage::Tls,
+            || tls_connect(host, tcp),
+            |_| "Handshake completed".to_string(),
+        );
+        self.report.certificate = match &tls {
+            Some(tls) => certificate::peer_certificate(tls),
+            None => connect_tcp(&[peer])
+                .ok()
+                .and_then(|tcp| certificate::rejected_certificate(host, tcp, before_handshake)),
+        };
+        tls
+    }
+}
+
+/// Resolve the server addresses
+fn resolve(host: &str, port: i64) -> Result<Vec<SocketAddr>> {
+    let port = u16::try_from(port).map_err(|_| anyhow!("invalid port: {}", port))?;
+    let addrs: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        return Err(anyhow!("no address found for {}", host));
+    }
+    Ok(addrs)
+}
+
+/// Connect to the first address accepting the connection within `CONNECT_TIMEOUT`
+/// Reads time out after `READ_TIMEOUT`, including the TLS handshake
+fn connect_tcp(addrs: &[SocketAddr]) -> Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+            Ok(tcp) => {
+                tcp.set_read_timeout(Some(READ_TIMEOUT))?;
+                return Ok(tcp);
             }
             Err(e) => {
-                error!("test_connection - Error: {:?}", e);
-                Err(anyhow!("Error: {:?}", e))
+                warn!("Failed to connect to {}: {}", addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(match last_error {
+        Some(e) if e.kind() == ErrorKind::TimedOut => {
+            anyhow!("connection timed out after {:?}", CONNECT_TIMEOUT)
+        }
+        Some(e) => e.into(),
+        None => anyhow!("no address to connect to"),
+    })
+}
+
+/// TLS handshake, failures are reported as `TlsError`
+fn tls_connect(host: &str, tcp: TcpStream) -> Result<TlsStream<TcpStream>> {
+    let ssl_connector = TlsConnector::builder().build()?;
+    Ok(ssl_connector
+        .connect(host, tcp)
+        .map_err(|e| TlsError(e.to_string()))?)
+}
+
+/// Create a client on the transport and read the server greeting
+fn read_greeting(
+    transport: Transport,
+    shutdown: Option<Shutdown>,
+) -> Result<(Client<ImapStream>, String)> {
+    let mut client = Client::new(ImapStream::new(transport, shutdown)?);
+    let greeting = String::from_utf8_lossy(&client.read_greeting()?).into_owned();
+    Ok((client, greeting))
+}
+
+/// Names of the capabilities advertised by the server, sorted
+fn capability_names(capabilities: &Capabilities) -> Vec<String> {
+    let mut names: Vec<String> = capabilities
+        .iter()
+        .map(|capability| match capability {
+            Capability::Imap4rev1 => "IMAP4rev1".to_string(),
+            Capability::Auth(mechanism) => format!("AUTH={}", mechanism),
+            Capability::Atom(name) => name.to_string(),
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Read the server greeting and ask for a TLS upgrade on a plain connection
+/// Returns the greeting
+///
+/// The `imap` crate only exposes STARTTLS for `Client<TcpStream>`, so the exchange
+/// is done by hand to keep using `ImapStream` once the handshake completes.
+fn starttls(tcp: &TcpStream) -> Result<String> {
+    let mut reader = BufReader::new(tcp);
+    let mut line = String::new();
+
+    reader.read_line(&mut line)?;
+    if !line.starts_with("* OK") {
+        return Err(anyhow!("unexpected greeting: {}", line.trim_end()));
+    }
+    let greeting = line.clone();
+
+    let mut writer = tcp;
+    writer.write_all(b"a0 STARTTLS\r\n")?;
+    writer.flush()?;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("connection closed during STARTTLS"));
+        }
+        if line.starts_with("a0 ") {
+            if line.starts_with("a0 OK") {
+                return Ok(greeting);
             }
+            return Err(anyhow!("STARTTLS rejected: {}", line.trim_end()));
         }
     }
 }
+
+/// Check if the host points to the local machine
+fn is_localhost(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread::JoinHandle};
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    /// Scripted IMAP server: sends a greeting, then answers each line sent by
+    /// the client with the next reply of the script
+    /// Returns the port and a handle resolving to the lines received
+    fn mock_server(replies: &[&'static str]) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let replies = replies.to_vec();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"* OK mock server ready\r\n").unwrap();
+
+            let mut received = vec![];
+            for reply in replies {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                received.push(line.trim_end().to_string());
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            received
+        });
+
+        (port, handle)
+    }
+
+    fn login(port: u16, auth_method: AuthMethod) -> Result<Session<ImapStream>> {
+        let tcp = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut client = Client::new(ImapStream::new(Transport::Plain(tcp), None).unwrap());
+        client.read_greeting().unwrap();
+
+        Imap::login(client, &details(port, auth_method))
+    }
+
+    fn details(port: u16, auth_method: AuthMethod) -> ConnectionDetails<'static> {
+        ConnectionDetails {
+            server: ("127.0.0.1", port as i64),
+            username: "user@example.com",
+            password: "access-token",
+            mailbox: "INBOX",
+            security: Security::None,
+            auth_method,
+        }
+    }
+
+    fn decode(line: &str) -> String {
+        String::from_utf8(STANDARD.decode(line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn xoauth2_sends_the_bearer_token() {
+        let (port, server) = mock_server(&["+ \r\n", "a1 OK authenticated\r\n"]);
+
+        assert!(login(port, AuthMethod::XOAuth2).is_ok());
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0], "a1 AUTHENTICATE XOAUTH2");
+        assert_eq!(
+            decode(&received[1]),
+            "user=user@example.com\x01auth=Bearer access-token\x01\x01"
+        );
+    }
+
+    #[test]
+    fn oauthbearer_sends_the_gs2_header() {
+        let (port, server) = mock_server(&["+ \r\n", "a1 OK authenticated\r\n"]);
+
+        assert!(login(port, AuthMethod::OAuthBearer).is_ok());
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0], "a1 AUTHENTICATE OAUTHBEARER");
+        assert_eq!(
+            decode(&received[1]),
+            "n,a=user@example.com,\x01auth=Bearer access-token\x01\x01"
+        );
+    }
+
+    #[test]
+    fn oauthbearer_escapes_the_authzid() {
+        let oauth = OAuth2 {
+            method: AuthMethod::OAuthBearer,
+            user: "a=b,c@example.com",
+            access_token: "access-token",
+        };
+
+        assert_eq!(
+            oauth.process(b""),
+            "n,a=a=3Db=2Cc@example.com,\x01auth=Bearer access-token\x01\x01"
+        );
+    }
+
+    #[test]
+    fn rejected_token_is_an_auth_failure() {
+        // The server sends the error details as a challenge, answered with an empty response
+        let (port, server) = mock_server(&[
+            "+ \r\n",
+            "+ eyJzdGF0dXMiOiI0MDEifQ==\r\n",
+            "a1 NO [AUTHENTICATIONFAILED] invalid credentials\r\n",
+        ]);
+
+        let error = login(port, AuthMethod::XOAuth2).unwrap_err();
+
+        let received = server.join().unwrap();
+        assert_eq!(received[2], "");
+        assert!(matches!(
+            error.downcast_ref::<ConnectionError>(),
+            Some(ConnectionError::AuthFailed(_))
+        ));
+    }
+
+    #[test]
+    fn unseen_count_searches_the_selected_mailbox() {
+        let (port, server) = mock_server(&[
+            "a1 OK logged in\r\n",
+            "* SEARCH 3 5 8\r\na2 OK search completed\r\n",
+        ]);
+
+        let mut session = login(port, AuthMethod::Password).unwrap();
+        assert_eq!(Imap::unseen_count(&mut session).unwrap(), 3);
+
+        let received = server.join().unwrap();
+        assert_eq!(received[1], "a2 UID SEARCH UNSEEN");
+    }
+
+    /// Why `unseen_count` does not use `SEARCH RETURN (COUNT)`, revisit when this fails
+    #[test]
+    fn esearch_responses_are_not_understood() {
+        let (port, server) = mock_server(&[
+            "a1 OK logged in\r\n",
+            "* ESEARCH (TAG \"a2\") UID COUNT 3\r\na2 OK search completed\r\n",
+        ]);
+
+        let mut session = login(port, AuthMethod::Password).unwrap();
+        assert!(session
+            .run_command_and_read_response("UID SEARCH RETURN (COUNT) UNSEEN")
+            .is_err());
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn connection_test_reports_every_stage() {
+        let (port, server) = mock_server(&[
+            "a1 OK logged in\r\n",
+            "* CAPABILITY IMAP4rev1 IDLE AUTH=PLAIN\r\na2 OK done\r\n",
+            "* 12 EXISTS\r\na3 OK [READ-WRITE] selected\r\n",
+            "* SEARCH 4 9\r\na4 OK done\r\n",
+            "* BYE\r\na5 OK done\r\n",
+        ]);
+        let (_stop, shutdown) = Shutdown::new();
+
+        let report = Imap::test_connection(&details(port, AuthMethod::Password), shutdown);
+        server.join().unwrap();
+
+        let stages: Vec<_> = report.steps.iter().map(|step| step.stage).collect();
+        assert_eq!(
+            stages,
+            vec![
+                TestStage::Dns,
+                TestStage::Tcp,
+                TestStage::Greeting,
+                TestStage::Authentication,
+                TestStage::Capabilities,
+                TestStage::Select,
+            ]
+        );
+        assert!(report.is_ok());
+        assert_eq!(report.capabilities, vec!["AUTH=PLAIN", "IDLE", "IMAP4rev1"]);
+        assert_eq!(report.watch_mode, Some(WatchMode::Idle));
+        assert_eq!((report.messages, report.unseen), (Some(12), Some(2)));
+    }
+
+    #[test]
+    fn connection_test_reports_the_failed_stage() {
+        let (port, server) = mock_server(&["a1 NO [AUTHENTICATIONFAILED] invalid\r\n"]);
+        let (_stop, shutdown) = Shutdown::new();
+
+        let report = Imap::test_connection(&details(port, AuthMethod::Password), shutdown);
+        server.join().unwrap();
+
+        let failed = report.failed_step().unwrap();
+        assert_eq!(failed.stage, TestStage::Authentication);
+        assert_eq!(report.steps.last(), Some(failed));
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn password_accounts_log_in() {
+        let (port, server) = mock_server(&["a1 OK logged in\r\n"]);
+
+        assert!(login(port, AuthMethod::Password).is_ok());
+
+        let received = server.join().unwrap();
+        assert_eq!(
+            received[0],
+            "a1 LOGIN \"user@example.com\" \"access-token\""
+        );
+    }
+}
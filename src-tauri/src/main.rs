@@ -1,29 +1,56 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use db::{initialize_db, run_migrations};
 use flume::{unbounded, Sender};
 use lazy_static::lazy_static;
 use log::{debug, error, info, LevelFilter};
 use simple_logger::SimpleLogger;
 use sqlx::{Pool, Sqlite, SqlitePool};
-use tauri::{
-    api::{notification::Notification, path::home_dir},
-    App, AppHandle, CustomMenuItem, GlobalWindowEvent, Manager, PhysicalPosition, PhysicalSize,
-    SystemTray, SystemTrayEvent, SystemTrayMenu, Window,
-};
+use tauri::{api::path::home_dir, App, GlobalWindowEvent, Manager};
 
-use crate::{db::account, watcher::Watcher};
+use crate::{
+    db::{account, mailbox},
+    message::MessageHeaders,
+    notifier::Notifier,
+    sound::{SoundPlayer, BUNDLED_SOUND},
+    status::Statuses,
+    tray::Tray,
+    watcher::Watcher,
+};
 
+pub mod certificate;
+pub mod coalesce;
 pub mod commands;
 pub mod db;
 pub mod error;
 pub mod imap;
 pub mod keychain;
 pub mod macros;
+pub mod message;
 pub mod models;
+pub mod notifier;
+pub mod oauth;
+pub mod pause;
+pub mod quiet_hours;
+pub mod rules;
+pub mod sound;
+pub mod status;
+pub mod tray;
+pub mod tray_icon;
+pub mod unread;
+pub mod vip;
 pub mod watcher;
 
+/// How often the notifier checks for the end of quiet hours
+const NOTIFIER_TICK: Duration = Duration::from_secs(60);
+
 lazy_static! {
     static ref APP_DIR: String = format!("{}/mailwatch", home_dir().unwrap().display());
     static ref DB_DIR: String = format!("{}/.db", APP_DIR.as_str());
@@ -32,12 +59,42 @@ lazy_static! {
 pub struct AppState {
     pub pool: SqlitePool,
     pub sender: Sender<UnboundedChannel>,
+    pub player: SoundPlayer,
+    pub tray: Tray,
+    pub statuses: Statuses,
+    /// Running connection tests, dropping a sender cancels the test
+    pub connection_tests: Mutex<HashMap<String, Sender<()>>>,
 }
 
 #[derive(Debug, Clone)]
 pub enum ChannelCmd {
-    Notify,
-    RestartWatcher,
+    Notify {
+        mailbox: String,
+        /// Uids of the messages that arrived since the last notification
+        uids: Vec<u32>,
+        /// Headers of the new messages
+        messages: Vec<MessageHeaders>,
+    },
+    /// A session changed state, see `models::AccountStatus`
+    StatusChanged {
+        mailbox: String,
+        update: models::StatusUpdate,
+    },
+    /// A session checked its mailbox without changing state
+    Checked { mailbox: String },
+    /// Number of unseen messages in a mailbox, sent when it changes
+    UnseenCount { mailbox: String, count: u32 },
+    /// Persist the last notified message of a mailbox
+    SaveMailboxState(models::MailboxState),
+    /// Stop the sessions of an account and start them again with its current settings,
+    /// a deleted account is only stopped
+    RestartAccount(i64),
+    /// The sessions of an account stopped, with their last notified state
+    /// Sent after every other message of those sessions
+    AccountStopped {
+        id: i64,
+        states: Vec<models::MailboxState>,
+    },
 }
 
 pub type UnboundedChannel = (ChannelCmd, Option<models::Account>);
@@ -58,28 +115,85 @@ async fn main() -> anyhow::Result<()> {
 
     let (tx, rx) = unbounded::<UnboundedChannel>();
 
+    let player = SoundPlayer::new();
+    let tray = Tray::default();
+    let statuses = Statuses::default();
+
     let mut watcher = Watcher::new(tx.clone());
     start_watcher(&mut watcher, &pool).await;
 
     let pool_clone: Pool<Sqlite> = pool.clone();
+    let mut notifier = Notifier::new(pool.clone(), player.clone());
+    let loop_tray = tray.clone();
+    let loop_statuses = statuses.clone();
     let rtx = tokio::spawn(async move {
         debug!("Starting to check for new messages.");
-        while let Ok((cmd, acc)) = rx.recv() {
+        let mut ticker = tokio::time::interval(NOTIFIER_TICK);
+        loop {
+            let flush_at = notifier.next_flush();
+            let (cmd, acc) = tokio::select! {
+                received = rx.recv_async() => match received {
+                    Ok(received) => received,
+                    Err(_) => break,
+                },
+                _ = sleep_until(flush_at) => {
+                    notifier.flush().await;
+                    continue;
+                }
+                _ = ticker.tick() => {
+                    notifier.tick().await;
+                    continue;
+                }
+            };
             info!("Command received: {:?}", cmd);
             match cmd {
-                ChannelCmd::Notify => {
+                ChannelCmd::Notify {
+                    mailbox,
+                    uids,
+                    messages,
+                } => {
                     if let Some(account) = acc {
-                        let notification = Notification::new(&account.name)
-                            .body("New email received")
-                            .title(&account.name);
-
-                        if let Err(e) = notification.show() {
-                            error!("Failed to show notification for {}: {}", account.name, e);
-                        }
+                        loop_tray.set_latest_mail(&account.color);
+                        notifier.notify(&account, &mailbox, &uids, &messages).await;
                     }
                 }
-                ChannelCmd::RestartWatcher => {
-                    restart_watcher(&mut watcher, &pool_clone).await;
+                ChannelCmd::StatusChanged { mailbox, update } => {
+                    if let Some(account) = acc {
+                        let connected = !update.state.is_error();
+                        loop_tray.set_connected(account.id, &mailbox, connected);
+                        loop_statuses.update(account.id, &mailbox, update);
+                    }
+                }
+                ChannelCmd::Checked { mailbox } => {
+                    if let Some(account) = acc {
+                        loop_statuses.checked(account.id, &mailbox);
+                    }
+                }
+                ChannelCmd::UnseenCount { mailbox, count } => {
+                    if let Some(account) = acc {
+                        loop_tray.set_unseen(account.id, &account.name, &mailbox, count);
+                    }
+                }
+                ChannelCmd::SaveMailboxState(state) => {
+                    if let Err(e) = mailbox::save_state(&state, &pool_clone).await {
+                        error!("Failed to save mailbox state {:?}: {:?}", state, e);
+                    }
+                }
+                // The sessions are joined off the loop, other accounts keep being served
+                ChannelCmd::RestartAccount(id) => watcher.stop_account(id),
+                ChannelCmd::AccountStopped { id, states } => {
+                    // Stopped sessions may have notified messages not saved yet
+                    for state in states {
+                        if let Err(e) = mailbox::save_state(&state, &pool_clone).await {
+                            error!("Failed to save mailbox state {:?}: {:?}", state, e);
+                        }
+                    }
+                    // Nothing is received from the stopped sessions anymore
+                    if watcher.account_stopped(id) {
+                        loop_tray.remove_account(id);
+                        loop_statuses.remove_account(id);
+                        start_account(&mut watcher, id, &pool_clone).await;
+                    }
                 }
             };
         }
@@ -91,17 +205,37 @@ async fn main() -> anyhow::Result<()> {
             commands::account::cmd_list_accounts,
             commands::account::cmd_find_account,
             commands::account::cmd_delete_account,
+            commands::account::cmd_set_account_active,
             commands::account::cmd_update_account,
+            commands::account::cmd_authorize_account,
+            commands::account::cmd_account_statuses,
             commands::connection::cmd_test_connection,
+            commands::connection::cmd_cancel_connection_test,
+            commands::preferences::cmd_fetch_account_preferences,
+            commands::preferences::cmd_update_account_preferences,
+            commands::rules::cmd_list_rules,
+            commands::rules::cmd_create_rule,
+            commands::rules::cmd_update_rule,
+            commands::rules::cmd_delete_rule,
+            commands::rules::cmd_test_rule,
             commands::settings::cmd_update_settings,
-            commands::settings::cmd_fetch_settings
+            commands::settings::cmd_fetch_settings,
+            commands::settings::cmd_pause_notifications,
+            commands::settings::cmd_resume_notifications,
+            commands::vip::cmd_list_vip_senders,
+            commands::vip::cmd_create_vip_sender,
+            commands::vip::cmd_delete_vip_sender
         ])
         .manage(AppState {
             pool,
             sender: tx.clone(),
+            player,
+            tray,
+            statuses,
+            connection_tests: Mutex::default(),
         })
-        .system_tray(build_tray_icon())
-        .on_system_tray_event(on_system_tray_event)
+        .system_tray(Tray::build())
+        .on_system_tray_event(tray::on_event)
         .on_window_event(on_window_event)
         .setup(on_app_setup)
         .build(tauri::generate_context!())?
@@ -114,66 +248,42 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Shutdown and start the watcher again
+/// Restart the sessions of a single account
 ///
 /// The purpose of this function is to restart the watcher when the user
-/// adds, deletes or update an existing account
-async fn restart_watcher(watcher: &mut Watcher, pool: &Pool<Sqlite>) {
-    let _ = &watcher.shutdown();
-    start_watcher(watcher, pool).await;
+/// adds, deletes or update an existing account, without touching the other accounts
+/// Start the sessions of an account once its previous ones stopped
+async fn start_account(watcher: &mut Watcher, id: i64, pool: &Pool<Sqlite>) {
+    let acc = match account::find(id, pool).await {
+        Ok(acc) => acc,
+        Err(_) => {
+            info!("Account {} not found, sessions stopped", id);
+            return;
+        }
+    };
+    let states = mailbox::states(pool).await.unwrap_or_else(|e| {
+        error!("Failed to load mailbox states: {:?}", e);
+        vec![]
+    });
+    watcher.start_account(acc, &states);
 }
 
 async fn start_watcher(watcher: &mut Watcher, pool: &Pool<Sqlite>) {
     info!("Starting watcher");
     let accounts = account::all(pool).await.unwrap();
-    let _ = &watcher.start(accounts);
-}
-
-fn build_tray_icon() -> SystemTray {
-    let quit = CustomMenuItem::new("quit".to_string(), "Quit").accelerator("Cmd+Q");
-    let tray_menu = SystemTrayMenu::new().add_item(quit);
-    SystemTray::new().with_menu(tray_menu)
-}
-
-fn on_system_tray_event(app: &AppHandle, e: SystemTrayEvent) {
-    match e {
-        tauri::SystemTrayEvent::MenuItemClick { id, .. } => {
-            if id.eq("quit") {
-                std::process::exit(0);
-            }
-        }
-        // On left click toggle the window visibility
-        // Show menu on left click should be disabled on tauri config
-        tauri::SystemTrayEvent::LeftClick {
-            tray_id: _,
-            position,
-            size,
-            ..
-        } => {
-            toggle_window_visibility(&app.get_window("main").unwrap(), position, size);
-        }
-        _ => {}
-    };
+    let states = mailbox::states(pool).await.unwrap_or_else(|e| {
+        error!("Failed to load mailbox states: {:?}", e);
+        vec![]
+    });
+    let _ = &watcher.start(accounts, states);
 }
 
-fn toggle_window_visibility(
-    window: &Window,
-    position: PhysicalPosition<f64>,
-    size: PhysicalSize<f64>,
-) {
-    match window.is_visible().unwrap() {
-        true => window.hide().unwrap(),
-        false => {
-            let window_size = window.outer_size().unwrap();
-            let physical_pos = PhysicalPosition {
-                x: position.x as i32 + (size.width as i32 / 2) - (window_size.width as i32 / 2),
-                y: position.y as i32 - window_size.height as i32,
-            };
-            let _ = window.set_position(tauri::Position::Physical(physical_pos));
-            window.show().unwrap();
-            window.set_focus().unwrap();
-        }
-    };
+/// Sleep until `deadline`, forever when there is none
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
 }
 
 // Window event to prevent killing the app when the user clicks on "close"
@@ -191,5 +301,19 @@ fn on_app_setup(app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(target_os = "macos")]
     app.set_activation_policy(tauri::ActivationPolicy::Accessory);
 
+    match app.path_resolver().resolve_resource(BUNDLED_SOUND) {
+        Some(path) => app.state::<AppState>().player.set_bundled_sound(path),
+        None => error!("Bundled sound {} not found", BUNDLED_SOUND),
+    }
+
+    app.state::<AppState>().tray.set_app(app.handle());
+    app.state::<AppState>().statuses.set_app(app.handle());
+
+    let handle = app.handle();
+    tauri::async_runtime::spawn(async move {
+        let pool = handle.state::<AppState>().pool.clone();
+        pause::restore(&handle, &pool).await;
+    });
+
     Ok(())
 }
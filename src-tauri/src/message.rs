@@ -0,0 +1,280 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+
+/// Headers of a new message, used to build notification previews and evaluate rules
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageHeaders {
+    pub uid: u32,
+    /// Decoded `From` header
+    pub from: String,
+    /// Decoded `To` header
+    pub to: String,
+    /// Decoded `Subject` header
+    pub subject: String,
+    /// `List-Id` header of mailing list messages
+    /// https://datatracker.ietf.org/doc/html/rfc2919
+    pub list_id: String,
+}
+
+impl MessageHeaders {
+    /// Parse the raw header section returned by `BODY.PEEK[HEADER.FIELDS (...)]`
+    pub fn parse(uid: u32, raw: &[u8]) -> Self {
+        let mut headers = MessageHeaders {
+            uid,
+            ..Default::default()
+        };
+
+        for (name, value) in unfold(&String::from_utf8_lossy(raw)) {
+            match name.to_ascii_lowercase().as_str() {
+                "from" => headers.from = decode_words(&value),
+                "to" => headers.to = decode_words(&value),
+                "subject" => headers.subject = decode_words(&value),
+                "list-id" => headers.list_id = decode_words(&value),
+                _ => {}
+            }
+        }
+
+        headers
+    }
+
+    /// Display name of the sender, or the address when there is no name
+    /// `"Jane Doe" <jane@example.com>` becomes `Jane Doe`
+    pub fn sender(&self) -> &str {
+        match self.from.split_once('<') {
+            Some((name, address)) => {
+                let name = name.trim().trim_matches('"').trim();
+                if name.is_empty() {
+                    address.trim_end_matches('>').trim()
+                } else {
+                    name
+                }
+            }
+            None => self.from.trim(),
+        }
+    }
+
+    /// Lowercase email address of the sender, without the display name
+    pub fn sender_address(&self) -> String {
+        let address = match self.from.rsplit_once('<') {
+            Some((_, address)) => address.split('>').next().unwrap_or_default(),
+            None => &self.from,
+        };
+        address.trim().to_lowercase()
+    }
+}
+
+/// Split a header section into (name, value) pairs, joining folded lines
+/// https://datatracker.ietf.org/doc/html/rfc5322#section-2.2.3
+fn unfold(raw: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = vec![];
+
+    for line in raw.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    headers
+}
+
+/// Decode RFC 2047 encoded-words (`=?charset?B|Q?text?=`) found in a header value
+/// Whitespace between two adjacent encoded-words is ignored.
+/// https://datatracker.ietf.org/doc/html/rfc2047
+pub fn decode_words(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    let mut pending_space = String::new();
+    let mut after_word = false;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find("=?") else {
+            result.push_str(&pending_space);
+            result.push_str(rest);
+            break;
+        };
+
+        let (before, candidate) = rest.split_at(start);
+        match decode_word(candidate) {
+            Some((decoded, len)) => {
+                // Only whitespace between two encoded-words is dropped
+                if !(after_word && before.trim().is_empty()) {
+                    result.push_str(&pending_space);
+                    result.push_str(before);
+                }
+                result.push_str(&decoded);
+                pending_space.clear();
+                after_word = true;
+                rest = &candidate[len..];
+
+                let spaces = rest.len() - rest.trim_start().len();
+                pending_space.push_str(&rest[..spaces]);
+                rest = &rest[spaces..];
+            }
+            None => {
+                result.push_str(&pending_space);
+                result.push_str(before);
+                result.push_str("=?");
+                pending_space.clear();
+                after_word = false;
+                rest = &candidate[2..];
+            }
+        }
+    }
+
+    result
+}
+
+/// Decode a single encoded-word at the beginning of `input`
+/// Returns the decoded text and the length of the encoded-word
+fn decode_word(input: &str) -> Option<(String, usize)> {
+    let body = input.strip_prefix("=?")?;
+    let (charset, body) = body.split_once('?')?;
+    let (encoding, body) = body.split_once('?')?;
+    let end = body.find("?=")?;
+    let text = &body[..end];
+
+    // RFC 2231 language suffix, e.g. UTF-8*en
+    let charset = charset.split('*').next().unwrap_or(charset);
+
+    let bytes = match encoding {
+        "B" | "b" => STANDARD.decode(text).ok()?,
+        "Q" | "q" => decode_q(text)?,
+        _ => return None,
+    };
+
+    let encoding = Encoding::for_label(charset.as_bytes()).unwrap_or(encoding_rs::UTF_8);
+    let (decoded, _, _) = encoding.decode(&bytes);
+
+    let len = input.len() - body.len() + end + 2;
+    Some((decoded.into_owned(), len))
+}
+
+/// "Q" encoding, similar to quoted-printable where `_` represents a space
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+
+    while let Some(c) = chars.next() {
+        match c {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => bytes.push(c),
+        }
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_base64_words() {
+        assert_eq!(decode_words("=?UTF-8?B?SGVsbG8gd29ybGQ=?="), "Hello world");
+        assert_eq!(decode_words("=?utf-8?b?w6l0w6k=?="), "été");
+    }
+
+    #[test]
+    fn decodes_q_words() {
+        assert_eq!(
+            decode_words("=?UTF-8?Q?Caf=C3=A9_cr=C3=A8me?="),
+            "Café crème"
+        );
+        assert_eq!(decode_q("a_b=3Dc"), Some(b"a b=c".to_vec()));
+    }
+
+    #[test]
+    fn decodes_other_charsets() {
+        assert_eq!(decode_words("=?ISO-8859-1?Q?Caf=E9?="), "Café");
+        assert_eq!(decode_words("=?windows-1252?Q?=80_5?="), "€ 5");
+        assert_eq!(decode_words("=?ISO-2022-JP?B?GyRCJUYlOSVIGyhC?="), "テスト");
+        // RFC 2231 language suffix
+        assert_eq!(decode_words("=?ISO-8859-1*fr?Q?=E9t=E9?="), "été");
+    }
+
+    #[test]
+    fn drops_whitespace_between_adjacent_words() {
+        assert_eq!(
+            decode_words("=?UTF-8?Q?Hello?= =?UTF-8?Q?_world?="),
+            "Hello world"
+        );
+        assert_eq!(decode_words("=?UTF-8?B?YQ==?=\t  =?UTF-8?B?Yg==?="), "ab");
+    }
+
+    #[test]
+    fn keeps_text_around_words() {
+        assert_eq!(
+            decode_words("Re: =?UTF-8?Q?Caf=C3=A9?= is open"),
+            "Re: Café is open"
+        );
+        assert_eq!(
+            decode_words("=?UTF-8?Q?Jane?= and =?UTF-8?Q?John?= <team@example.com>"),
+            "Jane and John <team@example.com>"
+        );
+    }
+
+    #[test]
+    fn malformed_words_are_kept() {
+        for value in [
+            "=?UTF-8?X?unknown?=",
+            "=?UTF-8?Q?unterminated",
+            "=?UTF-8?B?not base64!?=",
+            "=?UTF-8?Q?bad=Zhex?=",
+            "price =? 10",
+            "=?",
+        ] {
+            assert_eq!(decode_words(value), value);
+        }
+        assert_eq!(decode_word("=?UTF-8?Q?=C?="), None);
+        assert_eq!(
+            decode_words("=?UTF-8?Q?bad=Z?= =?UTF-8?Q?ok?="),
+            "=?UTF-8?Q?bad=Z?= ok"
+        );
+    }
+
+    #[test]
+    fn returns_the_length_of_the_word() {
+        let (decoded, len) = decode_word("=?UTF-8?Q?abc?= rest").unwrap();
+        assert_eq!(decoded, "abc");
+        assert_eq!(len, "=?UTF-8?Q?abc?=".len());
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let raw = "Subject: a long\r\n  subject\r\n\tline\r\nFrom: jane@example.com\r\n";
+        assert_eq!(
+            unfold(raw),
+            vec![
+                ("Subject".to_string(), "a long subject line".to_string()),
+                ("From".to_string(), "jane@example.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_folded_encoded_headers() {
+        let raw = b"From: =?UTF-8?Q?Jos=C3=A9?=\r\n <jose@example.com>\r\n\
+            Subject: =?UTF-8?B?UsOpdW5pb24=?=\r\n =?UTF-8?B?IGRlbWFpbg==?=\r\n\
+            List-Id: <dev.example.com>\r\n\r\n";
+
+        let headers = MessageHeaders::parse(7, raw);
+
+        assert_eq!(headers.uid, 7);
+        assert_eq!(headers.from, "José <jose@example.com>");
+        assert_eq!(headers.sender(), "José");
+        assert_eq!(headers.sender_address(), "jose@example.com");
+        assert_eq!(headers.subject, "Réunion demain");
+        assert_eq!(headers.list_id, "<dev.example.com>");
+    }
+}
@@ -1,6 +1,55 @@
+use chrono::{DateTime, Duration, Local};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+/// Transport security used to talk to the IMAP server
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Security {
+    /// Implicit TLS, usually on port 993
+    #[default]
+    Tls,
+    /// Plain connection upgraded with the STARTTLS command, usually on port 143
+    StartTls,
+    /// No encryption at all. Only allowed for servers running on localhost
+    None,
+}
+
+/// How the user authenticates against the IMAP server
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// LOGIN with username and password
+    #[default]
+    Password,
+    /// SASL XOAUTH2, used by Gmail and Microsoft 365
+    XOAuth2,
+    /// SASL OAUTHBEARER
+    /// https://datatracker.ietf.org/doc/html/rfc7628
+    OAuthBearer,
+}
+
+/// Identity providers supported for OAuth2 accounts
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum OAuthProviderKind {
+    Google,
+    Microsoft,
+}
+
+/// How the watcher learns about new messages
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// The server pushes changes through IMAP IDLE
+    Idle,
+    /// The server does not support IDLE, the mailbox is checked periodically
+    Polling,
+}
+
 #[derive(Serialize, FromRow, Debug, Deserialize, Clone)]
 pub struct Account {
     pub id: i64,
@@ -11,11 +60,355 @@ pub struct Account {
     pub active: bool,
     pub username: String,
     pub mailbox: String,
+    pub security: Security,
+    pub auth_method: AuthMethod,
+    pub oauth_provider: Option<OAuthProviderKind>,
+    /// Seconds between checks when the server does not support IDLE
+    pub poll_interval: i64,
+    /// Watched mailboxes, loaded from the `account_mailboxes` table
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub mailboxes: Vec<String>,
+}
+
+/// Last notified message of a watched mailbox
+///
+/// UIDs are only meaningful for a given UIDVALIDITY, when the server changes it
+/// the last notified uid is reset.
+/// https://datatracker.ietf.org/doc/html/rfc3501#section-2.3.1.1
+#[derive(Serialize, FromRow, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct MailboxState {
+    pub account_id: i64,
+    pub mailbox: String,
+    pub uid_validity: i64,
+    pub last_uid: i64,
+}
+
+/// Notification preferences of an account
+/// `None` values fall back to the global `Settings`
+#[derive(Serialize, FromRow, Debug, Deserialize, Clone, Default)]
+pub struct AccountPreferences {
+    pub account_id: i64,
+    pub muted: Option<bool>,
+    pub sound: Option<bool>,
+    pub preview: Option<bool>,
+    /// Sound played for this account instead of the bundled one
+    pub sound_path: Option<String>,
+}
+
+impl AccountPreferences {
+    /// Resolve the preferences of the account: account override, then global setting
+    pub fn resolve(&self, settings: &Settings) -> NotificationPreferences {
+        NotificationPreferences {
+            enabled: self
+                .muted
+                .map(|muted| !muted)
+                .or(settings.notifications)
+                .unwrap_or(false),
+            sound: self.sound.or(settings.sound).unwrap_or(false),
+            preview: self.preview.or(settings.preview).unwrap_or(false),
+            sound_path: self.sound_path.clone(),
+            volume: settings.volume.unwrap_or(1.0),
+        }
+    }
+}
+
+/// Effective notification preferences of an account
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotificationPreferences {
+    pub enabled: bool,
+    pub sound: bool,
+    pub preview: bool,
+    pub sound_path: Option<String>,
+    pub volume: f64,
+}
+
+/// Header a rule is matched against
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum RuleField {
+    From,
+    To,
+    Subject,
+    ListId,
+}
+
+/// How the rule pattern is compared to the header
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum MatchType {
+    /// Case insensitive substring
+    Contains,
+    Regex,
+}
+
+/// What happens to a message matched by a rule
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum RuleAction {
+    /// No notification
+    Ignore,
+    /// Regular notification, useful to stop later rules from matching
+    Notify,
+    /// High urgency notification, shown even during quiet hours
+    Urgent,
+    /// Notification with the rule sound
+    Sound,
+}
+
+/// Notification rule, rules are evaluated by position and the first match wins
+#[derive(Serialize, FromRow, Debug, Deserialize, Clone)]
+pub struct Rule {
+    pub id: i64,
+    /// `None` applies the rule to every account
+    pub account_id: Option<i64>,
+    pub name: String,
+    pub field: RuleField,
+    pub match_type: MatchType,
+    pub pattern: String,
+    pub action: RuleAction,
+    /// Sound played by `RuleAction::Sound` rules
+    pub sound_path: Option<String>,
+    pub position: i64,
+    pub enabled: bool,
 }
 
+/// Sender whose mail always notifies, even when paused or during quiet hours
 #[derive(Serialize, FromRow, Debug, Deserialize, Clone)]
+pub struct VipSender {
+    pub id: i64,
+    /// `None` applies to every account
+    pub account_id: Option<i64>,
+    /// Email address, or a whole domain such as `example.com`
+    pub address: String,
+}
+
+/// Connection state of a watcher session
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting,
+    /// Connected, waiting for changes with IDLE
+    Idle,
+    /// Connected, checking every `poll_interval` seconds
+    Polling,
+    /// Transient failure, see `AccountStatus::retry_at`
+    Reconnecting,
+    /// Rejected credentials, the session stopped
+    AuthFailed,
+    /// TLS handshake failure, retried like `Reconnecting`
+    TlsError,
+    /// Other failure reconnecting will not fix, the session stopped
+    Failed,
+    /// The account is not watched
+    Disabled,
+}
+
+impl ConnectionState {
+    /// Whether the session is not connected because of a failure
+    pub fn is_error(self) -> bool {
+        matches!(
+            self,
+            Self::Reconnecting | Self::AuthFailed | Self::TlsError | Self::Failed
+        )
+    }
+}
+
+/// Transition reported by a watcher session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusUpdate {
+    pub state: ConnectionState,
+    pub error: Option<String>,
+    /// Seconds before the next connection attempt
+    pub retry_in: Option<u64>,
+}
+
+impl StatusUpdate {
+    pub fn new(state: ConnectionState) -> Self {
+        Self {
+            state,
+            error: None,
+            retry_in: None,
+        }
+    }
+}
+
+/// Connection status of a watched mailbox, timestamps are unix seconds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountStatus {
+    pub account_id: i64,
+    pub mailbox: String,
+    pub state: ConnectionState,
+    /// When the state last changed
+    pub since: i64,
+    /// Next connection attempt, while reconnecting
+    pub retry_at: Option<i64>,
+    /// Last time new messages were checked
+    pub last_checked: Option<i64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<i64>,
+}
+
+impl AccountStatus {
+    pub fn new(account_id: i64, mailbox: &str, now: i64) -> Self {
+        Self {
+            account_id,
+            mailbox: mailbox.to_string(),
+            state: ConnectionState::Connecting,
+            since: now,
+            retry_at: None,
+            last_checked: None,
+            last_error: None,
+            last_error_at: None,
+        }
+    }
+
+    /// Apply a transition reported at `now`
+    pub fn apply(&mut self, update: StatusUpdate, now: i64) {
+        if self.state != update.state {
+            self.state = update.state;
+            self.since = now;
+        }
+        self.retry_at = update.retry_in.map(|secs| now + secs as i64);
+        if matches!(
+            update.state,
+            ConnectionState::Idle | ConnectionState::Polling
+        ) {
+            self.last_checked = Some(now);
+        }
+        if let Some(error) = update.error {
+            self.last_error = Some(error);
+            self.last_error_at = Some(now);
+        }
+    }
+}
+
+/// Stages of a connection test
+/// They run in declaration order, except with STARTTLS where the greeting comes
+/// before the TLS handshake. Unencrypted connections have no TLS stage.
+/// Capabilities are listed once authenticated, servers may only
+/// advertise some of them (e.g. IDLE) after login
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStage {
+    Dns,
+    Tcp,
+    Tls,
+    Greeting,
+    Authentication,
+    Capabilities,
+    Select,
+}
+
+/// Outcome of a connection test stage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestStep {
+    pub stage: TestStage,
+    pub ok: bool,
+    /// What was found, or why the stage failed
+    pub detail: String,
+    pub elapsed_ms: u64,
+}
+
+/// Certificate presented by the server during the TLS handshake
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// Unix timestamps
+    pub not_before: i64,
+    pub not_after: i64,
+    /// DNS names of the subject alternative name extension
+    pub dns_names: Vec<String>,
+    /// SHA-256 of the DER encoded certificate, as colon separated hex
+    pub fingerprint: String,
+}
+
+/// Step by step report of a connection test
+/// Stages stop at the first failure, the failed stage is the last step
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConnectionReport {
+    pub steps: Vec<TestStep>,
+    /// Also set when the handshake failed, to tell why the certificate is rejected
+    pub certificate: Option<CertificateInfo>,
+    pub capabilities: Vec<String>,
+    pub watch_mode: Option<WatchMode>,
+    /// Number of messages in the selected mailbox
+    pub messages: Option<u32>,
+    pub unseen: Option<u32>,
+}
+
+impl ConnectionReport {
+    /// Whether every stage succeeded
+    pub fn is_ok(&self) -> bool {
+        self.steps
+            .last()
+            .is_some_and(|step| step.stage == TestStage::Select && step.ok)
+    }
+
+    /// The stage that failed, if any
+    pub fn failed_step(&self) -> Option<&TestStep> {
+        self.steps.iter().find(|step| !step.ok)
+    }
+}
+
+#[derive(Serialize, FromRow, Debug, Deserialize, Clone, Default)]
 pub struct Settings {
     pub notifications: Option<bool>,
     pub sound: Option<bool>,
     pub preview: Option<bool>,
+    /// Notification sound volume, from 0.0 to 1.0
+    pub volume: Option<f64>,
+    /// Unix timestamp (seconds) until which notifications are paused
+    pub paused_until: Option<i64>,
+    /// Quiet hours, see `quiet_hours::QuietHours`
+    pub quiet_hours: Option<bool>,
+    /// Local `HH:MM` start of the quiet hours
+    pub quiet_start: Option<String>,
+    /// Local `HH:MM` end of the quiet hours
+    pub quiet_end: Option<String>,
+    /// Days on which quiet hours start, Monday is bit 0 and Sunday bit 6
+    pub quiet_days: Option<i64>,
+    /// Show a summary of the mail received once quiet hours end
+    pub quiet_summary: Option<bool>,
+    /// Seconds during which new messages of an account are merged
+    /// into a single notification, 0 shows them right away
+    pub coalesce_window: Option<i64>,
+    /// Maximum number of notifications per minute across accounts, 0 for no limit
+    pub rate_limit: Option<i64>,
+}
+
+impl Settings {
+    /// Whether notifications are paused at the given unix timestamp
+    pub fn is_paused(&self, now: i64) -> bool {
+        self.paused_until.is_some_and(|until| now < until)
+    }
+}
+
+/// How long notifications are paused for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseDuration {
+    ThirtyMinutes,
+    OneHour,
+    /// Until midnight, local time
+    UntilTomorrow,
+}
+
+impl PauseDuration {
+    /// End of the pause started at `now`
+    pub fn until(self, now: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            PauseDuration::ThirtyMinutes => now + Duration::minutes(30),
+            PauseDuration::OneHour => now + Duration::hours(1),
+            PauseDuration::UntilTomorrow => (now.date_naive() + Duration::days(1))
+                .and_hms_opt(0, 0, 0)
+                .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+                // Midnight may not exist on DST changes
+                .unwrap_or(now + Duration::days(1)),
+        }
+    }
 }
@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use log::{error, info};
+use sqlx::{Pool, Sqlite};
+use tauri::api::notification::Notification;
+
+use crate::{
+    coalesce::{Coalescer, Pending, Sound},
+    db::{preferences, rules, settings, vip},
+    message::MessageHeaders,
+    models::{Account, AccountPreferences, Settings},
+    quiet_hours,
+    rules::{evaluate, urgent_messages, Verdict},
+    sound::SoundPlayer,
+    vip::vip_messages,
+};
+
+/// Title of the notification shown when quiet hours end
+const QUIET_SUMMARY_TITLE: &str = "While quiet hours were on";
+
+/// Title prefix of notifications matched by an urgent rule or sent by a VIP
+/// Tauri notifications have no urgency level, the title makes them stand out
+const URGENT_PREFIX: &str = "Important: ";
+
+/// Default coalescing window, in seconds
+const DEFAULT_COALESCE_WINDOW: i64 = 10;
+
+/// Default number of notifications per minute across accounts
+const DEFAULT_RATE_LIMIT: i64 = 6;
+
+/// Mail received by an account during quiet hours
+#[derive(Debug)]
+struct QuietCount {
+    name: String,
+    count: usize,
+}
+
+/// Shows desktop notifications and plays sounds for new messages
+#[derive(Debug)]
+pub struct Notifier {
+    pool: Pool<Sqlite>,
+    player: SoundPlayer,
+    /// Messages received during quiet hours, keyed by account id
+    quiet: BTreeMap<i64, QuietCount>,
+    /// Notifications waiting for the end of their coalescing window
+    coalescer: Coalescer,
+}
+
+impl Notifier {
+    pub fn new(pool: Pool<Sqlite>, player: SoundPlayer) -> Self {
+        Self {
+            pool,
+            player,
+            quiet: BTreeMap::new(),
+            coalescer: Coalescer::default(),
+        }
+    }
+
+    /// Queue the notification of new messages, according to the settings,
+    /// the account preferences, the notification rules and the VIP senders
+    /// Messages received by the account during the coalescing window
+    /// are shown together, see `Coalescer`
+    pub async fn notify(
+        &mut self,
+        account: &Account,
+        mailbox: &str,
+        uids: &[u32],
+        messages: &[MessageHeaders],
+    ) {
+        let settings = self.settings().await;
+        let prefs = match preferences::find(account.id, &self.pool).await {
+            Ok(prefs) => prefs.resolve(&settings),
+            Err(e) => {
+                error!("Failed to load preferences of {}: {:?}", account.name, e);
+                AccountPreferences::default().resolve(&settings)
+            }
+        };
+
+        if !prefs.enabled {
+            info!("Notifications disabled for {}", account.name);
+            return;
+        }
+
+        let Some(verdict) = self.verdict(account, &settings, uids, messages).await else {
+            return;
+        };
+
+        // Rule sounds play even when sounds are turned off for the account
+        let path = match verdict.sound_path {
+            Some(path) => Some(Some(path)),
+            None if prefs.sound || verdict.urgent => Some(prefs.sound_path),
+            None => None,
+        };
+        let sound = path.map(|path| Sound {
+            path,
+            volume: prefs.volume,
+        });
+
+        let latest = messages
+            .iter()
+            .rev()
+            .find(|m| prefs.preview && verdict.notified.contains(&m.uid))
+            .cloned();
+
+        let window = settings
+            .coalesce_window
+            .unwrap_or(DEFAULT_COALESCE_WINDOW)
+            .max(0);
+        let rate_limit = settings.rate_limit.unwrap_or(DEFAULT_RATE_LIMIT).max(0);
+        self.coalescer.set_rate_limit(rate_limit as usize);
+        self.coalescer.push(
+            account.id,
+            Pending {
+                account: account.name.clone(),
+                mailboxes: vec![mailbox.to_string()],
+                count: verdict.notified.len(),
+                latest,
+                urgent: verdict.urgent,
+                sound,
+            },
+            Duration::from_secs(window as u64),
+            Instant::now(),
+        );
+
+        self.flush().await;
+    }
+
+    /// When `flush` should be called next, `None` when nothing is queued
+    pub fn next_flush(&self) -> Option<Instant> {
+        self.coalescer.next_deadline()
+    }
+
+    /// Show the queued notifications whose coalescing window ended
+    /// Only urgent ones are shown when notifications were paused
+    /// or quiet hours started meanwhile
+    pub async fn flush(&mut self) {
+        let due = self.coalescer.due(Instant::now());
+        if due.is_empty() {
+            return;
+        }
+
+        let settings = self.settings().await;
+        let paused = settings.is_paused(Utc::now().timestamp());
+        let quiet = quiet_hours::is_quiet(&settings);
+
+        for (account_id, pending) in due {
+            match pending.urgent {
+                false if paused => {
+                    info!("Notifications paused, dropping {}", pending.account)
+                }
+                false if quiet => self.count_quiet(account_id, &pending.account, pending.count),
+                _ => self.show_pending(pending),
+            }
+        }
+    }
+
+    fn show_pending(&self, pending: Pending) {
+        let title = match pending.urgent {
+            true => format!("{}{}", URGENT_PREFIX, pending.account),
+            false => pending.account.clone(),
+        };
+        // Bursts spanning several mailboxes are described by account
+        let place = match pending.mailboxes.as_slice() {
+            [mailbox] => mailbox,
+            _ => &pending.account,
+        };
+        show(
+            &title,
+            &notification_body(place, pending.count, pending.latest.as_ref()),
+        );
+
+        if let Some(sound) = pending.sound {
+            self.player.play(sound.path.as_deref(), sound.volume);
+        }
+    }
+
+    /// Count mail received during quiet hours, for the summary
+    fn count_quiet(&mut self, account_id: i64, name: &str, count: usize) {
+        info!("Quiet hours, counting {} messages for {}", count, name);
+        if count > 0 {
+            self.quiet
+                .entry(account_id)
+                .or_insert_with(|| QuietCount {
+                    name: name.to_string(),
+                    count: 0,
+                })
+                .count += count;
+        }
+    }
+
+    /// Decide which messages are notified
+    /// VIP mail is always notified as important, even when paused or during
+    /// quiet hours, the rest goes through the rules and quiet hours
+    /// Messages matched by an urgent rule get through pauses and quiet hours too
+    async fn verdict(
+        &mut self,
+        account: &Account,
+        settings: &Settings,
+        uids: &[u32],
+        messages: &[MessageHeaders],
+    ) -> Option<Verdict> {
+        let vips = vip::all(&self.pool).await.unwrap_or_else(|e| {
+            error!("Failed to load VIP senders: {:?}", e);
+            vec![]
+        });
+        let vip_uids = vip_messages(&vips, account.id, uids, messages);
+        let vip_only = || match vip_uids.is_empty() {
+            true => None,
+            false => Some(Verdict {
+                notified: vip_uids.clone(),
+                urgent: true,
+                sound_path: None,
+            }),
+        };
+
+        let rules = rules::all(&self.pool).await.unwrap_or_else(|e| {
+            error!("Failed to load rules: {:?}", e);
+            vec![]
+        });
+
+        if settings.is_paused(Utc::now().timestamp()) {
+            let urgent_uids = urgent_messages(&rules, account.id, uids, messages);
+            let notified: Vec<u32> = uids
+                .iter()
+                .filter(|uid| vip_uids.contains(uid) || urgent_uids.contains(uid))
+                .copied()
+                .collect();
+            info!(
+                "Notifications paused, notifying {} VIP or urgent messages of {}",
+                notified.len(),
+                account.name
+            );
+            return match notified.is_empty() {
+                true => None,
+                false => Some(Verdict {
+                    notified,
+                    urgent: true,
+                    sound_path: None,
+                }),
+            };
+        }
+
+        let mut verdict = evaluate(&rules, account.id, uids, messages);
+
+        // Quiet hours only silence notifications, the mail is still counted
+        if !verdict.urgent && quiet_hours::is_quiet(settings) {
+            let count = verdict
+                .notified
+                .iter()
+                .filter(|uid| !vip_uids.contains(*uid))
+                .count();
+            self.count_quiet(account.id, &account.name, count);
+            return vip_only();
+        }
+
+        // Rules never ignore VIP mail
+        if !vip_uids.is_empty() {
+            verdict.notified = uids
+                .iter()
+                .filter(|&&uid| verdict.notified.contains(&uid) || vip_uids.contains(&uid))
+                .copied()
+                .collect();
+            verdict.urgent = true;
+        }
+
+        if verdict.notified.is_empty() {
+            info!("Every new message of {} ignored by rules", account.name);
+            return None;
+        }
+        Some(verdict)
+    }
+
+    /// Called periodically, shows the summary of the mail received
+    /// during quiet hours once they are over
+    pub async fn tick(&mut self) {
+        if self.quiet.is_empty() {
+            return;
+        }
+
+        let settings = self.settings().await;
+        if quiet_hours::is_quiet(&settings) || settings.is_paused(Utc::now().timestamp()) {
+            return;
+        }
+
+        let quiet = std::mem::take(&mut self.quiet);
+        if settings.quiet_summary.unwrap_or(true) {
+            let body = quiet
+                .values()
+                .map(|q| match q.count {
+                    1 => format!("1 new message in {}", q.name),
+                    n => format!("{} new messages in {}", n, q.name),
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            show(QUIET_SUMMARY_TITLE, &body);
+        }
+    }
+
+    async fn settings(&self) -> Settings {
+        settings::fetch(&self.pool)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+}
+
+fn show(title: &str, body: &str) {
+    let notification = Notification::new(title).body(body).title(title);
+
+    if let Err(e) = notification.show() {
+        error!("Failed to show notification {}: {}", title, e);
+    }
+}
+
+fn notification_body(mailbox: &str, count: usize, latest: Option<&MessageHeaders>) -> String {
+    match (count, latest) {
+        (1, Some(message)) => format!("{}\n{}", message.sender(), message.subject),
+        (1, None) => format!("New email received in {}", mailbox),
+        (_, Some(message)) => format!(
+            "{} new messages in {}\nLatest from {}: {}",
+            count,
+            mailbox,
+            message.sender(),
+            message.subject
+        ),
+        (_, None) => format!("{} new messages in {}", count, mailbox),
+    }
+}
@@ -0,0 +1,629 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use log::{info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{error::ConnectionError, keychain::Keychain, models::OAuthProviderKind};
+
+/// Refresh access tokens that expire in less than this amount of seconds
+const EXPIRY_MARGIN: i64 = 60;
+
+/// Time given to a connection to the redirect listener to send its request line
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Page displayed in the browser once the redirect has been received
+const REDIRECT_RESPONSE: &str =
+    "<html><body><h3>MailWatch</h3><p>You can close this window now.</p></body></html>";
+
+/// OAuth2 endpoints and client registration of an identity provider
+///
+/// Client ids are injected at build time through the
+/// `MAILWATCH_GOOGLE_CLIENT_ID`, `MAILWATCH_GOOGLE_CLIENT_SECRET` and
+/// `MAILWATCH_MICROSOFT_CLIENT_ID` environment variables.
+#[derive(Debug, Clone)]
+pub struct OAuthProvider {
+    pub auth_url: String,
+    pub token_url: String,
+    pub scopes: Vec<String>,
+    pub client_id: String,
+    /// Installed apps are public clients, but Google still expects a (non confidential) secret
+    pub client_secret: Option<String>,
+}
+
+impl OAuthProvider {
+    pub fn google() -> Self {
+        Self {
+            auth_url: "https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+            token_url: "https://oauth2.googleapis.com/token".to_string(),
+            scopes: vec!["https://mail.google.com/".to_string()],
+            client_id: option_env!("MAILWATCH_GOOGLE_CLIENT_ID")
+                .unwrap_or_default()
+                .to_string(),
+            client_secret: option_env!("MAILWATCH_GOOGLE_CLIENT_SECRET").map(str::to_string),
+        }
+    }
+
+    pub fn microsoft() -> Self {
+        Self {
+            auth_url: "https://login.microsoftonline.com/common/oauth2/v2.0/authorize".to_string(),
+            token_url: "https://login.microsoftonline.com/common/oauth2/v2.0/token".to_string(),
+            scopes: vec![
+                "https://outlook.office.com/IMAP.AccessAsUser.All".to_string(),
+                "offline_access".to_string(),
+            ],
+            client_id: option_env!("MAILWATCH_MICROSOFT_CLIENT_ID")
+                .unwrap_or_default()
+                .to_string(),
+            client_secret: None,
+        }
+    }
+
+    pub fn from_kind(kind: OAuthProviderKind) -> Self {
+        match kind {
+            OAuthProviderKind::Google => OAuthProvider::google(),
+            OAuthProviderKind::Microsoft => OAuthProvider::microsoft(),
+        }
+    }
+}
+
+/// Tokens of an OAuth account
+/// They are stored as JSON in the keychain entry that holds the password of other accounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Unix timestamp (seconds) when the access token expires
+    pub expires_at: i64,
+}
+
+impl OAuthTokens {
+    fn is_expired(&self) -> bool {
+        self.expires_at - EXPIRY_MARGIN <= now()
+    }
+}
+
+/// Token endpoint response
+/// https://datatracker.ietf.org/doc/html/rfc6749#section-5.1
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+/// An authorization request waiting for the browser to redirect back to the loopback address
+///
+/// Authorization code flow with PKCE for native apps
+/// https://datatracker.ietf.org/doc/html/rfc8252
+#[derive(Debug)]
+pub struct PendingAuthorization {
+    /// URL the user has to open in the browser
+    pub url: String,
+    provider: OAuthProvider,
+    listener: TcpListener,
+    redirect_uri: String,
+    verifier: String,
+    state: String,
+}
+
+impl PendingAuthorization {
+    /// Bind the loopback redirect listener and build the authorization URL
+    pub fn new(provider: OAuthProvider, login_hint: Option<&str>) -> Result<Self> {
+        if provider.client_id.is_empty() {
+            return Err(anyhow!(
+                "OAuth client id is not configured for this provider"
+            ));
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let redirect_uri = format!("http://127.0.0.1:{}", listener.local_addr()?.port());
+        let verifier = random_string(64);
+        let state = random_string(32);
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+        let mut url = Url::parse(&provider.auth_url)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &redirect_uri)
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256")
+            .append_pair("access_type", "offline")
+            .append_pair("prompt", "consent");
+        if let Some(hint) = login_hint {
+            url.query_pairs_mut().append_pair("login_hint", hint);
+        }
+
+        Ok(Self {
+            url: url.to_string(),
+            provider,
+            listener,
+            redirect_uri,
+            verifier,
+            state,
+        })
+    }
+
+    /// Wait for the browser redirect and exchange the authorization code for tokens
+    pub fn wait(self, timeout: Duration) -> Result<OAuthTokens> {
+        let deadline = Instant::now() + timeout;
+        let redirect = loop {
+            let mut stream = self.accept(deadline)?;
+            if let Some(redirect) = self.read_redirect(&mut stream, deadline) {
+                break redirect;
+            }
+        };
+
+        let param = |name: &str| {
+            redirect
+                .query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+        };
+
+        if let Some(error) = param("error") {
+            return Err(anyhow!("authorization denied: {}", error));
+        }
+        if param("state").as_deref() != Some(self.state.as_str()) {
+            return Err(anyhow!("authorization state mismatch"));
+        }
+        let code = param("code").ok_or_else(|| anyhow!("authorization code missing"))?;
+
+        exchange(&self.provider, &code, &self.redirect_uri, &self.verifier)
+    }
+
+    /// Read the redirect of the authorization server from a connection
+    /// Returns `None` for connections that send nothing in time or another request,
+    /// e.g. a browser preconnect or a favicon request, so the next one is accepted
+    fn read_redirect(&self, stream: &mut TcpStream, deadline: Instant) -> Option<Url> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timeout = remaining.min(REQUEST_TIMEOUT).max(Duration::from_millis(1));
+        stream.set_read_timeout(Some(timeout)).ok()?;
+
+        let mut request_line = String::new();
+        if let Err(e) = BufReader::new(&*stream).read_line(&mut request_line) {
+            warn!("Dropping redirect connection: {}", e);
+            return None;
+        }
+
+        // GET /?code=...&state=... HTTP/1.1
+        let path = request_line.split_whitespace().nth(1)?;
+        let redirect = Url::parse(&self.redirect_uri).ok()?.join(path).ok()?;
+        if !redirect
+            .query_pairs()
+            .any(|(k, _)| k == "state" || k == "code" || k == "error")
+        {
+            warn!("Dropping unexpected request: {}", request_line.trim_end());
+            return None;
+        }
+
+        let _ = stream.write_all(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                REDIRECT_RESPONSE.len(),
+                REDIRECT_RESPONSE
+            )
+            .as_bytes(),
+        );
+        Some(redirect)
+    }
+
+    fn accept(&self, deadline: Instant) -> Result<TcpStream> {
+        self.listener.set_nonblocking(true)?;
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(stream);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(anyhow!("timed out waiting for the authorization"));
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Exchange an authorization code for tokens
+pub fn exchange(
+    provider: &OAuthProvider,
+    code: &str,
+    redirect_uri: &str,
+    verifier: &str,
+) -> Result<OAuthTokens> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", &provider.client_id),
+        ("code_verifier", verifier),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret));
+    }
+
+    request_tokens(provider, &form, None)
+}
+
+/// Exchange a refresh token for a new access token
+pub fn refresh(provider: &OAuthProvider, tokens: &OAuthTokens) -> Result<OAuthTokens> {
+    let refresh_token = tokens
+        .refresh_token
+        .as_deref()
+        .ok_or_else(|| anyhow!("no refresh token available, authorize the account again"))?;
+
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", &provider.client_id),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret));
+    }
+
+    // Providers may omit the refresh token when it did not change
+    request_tokens(provider, &form, tokens.refresh_token.clone())
+}
+
+/// Return a valid access token for the account, refreshing and storing it when expired
+pub fn access_token(provider: &OAuthProvider, keychain: &Keychain) -> Result<String> {
+    let tokens = load_tokens(keychain)?;
+    match fresh_tokens(provider, &tokens)? {
+        Some(refreshed) => {
+            info!("Access token refreshed for {}", keychain.username);
+            store_tokens(keychain, &refreshed)?;
+            Ok(refreshed.access_token)
+        }
+        None => Ok(tokens.access_token),
+    }
+}
+
+/// Refresh the tokens when the access token is about to expire
+/// Returns `None` when the current tokens are still valid
+pub fn fresh_tokens(provider: &OAuthProvider, tokens: &OAuthTokens) -> Result<Option<OAuthTokens>> {
+    match tokens.is_expired() {
+        true => refresh(provider, tokens).map(Some),
+        false => Ok(None),
+    }
+}
+
+/// Read the tokens of an account from the keychain
+/// Accounts that were never authorized fail with `ConnectionError::MissingCredentials`
+pub fn load_tokens(keychain: &Keychain) -> Result<OAuthTokens> {
+    let json = keychain
+        .get_password()
+        .map_err(|e| match e.downcast_ref::<keyring::Error>() {
+            Some(keyring::Error::NoEntry) => ConnectionError::MissingCredentials.into(),
+            _ => e,
+        })?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Save the tokens of an account in the keychain
+pub fn store_tokens(keychain: &Keychain, tokens: &OAuthTokens) -> Result<()> {
+    keychain.new_entry(&serde_json::to_string(tokens)?)?;
+    Ok(())
+}
+
+fn request_tokens(
+    provider: &OAuthProvider,
+    form: &[(&str, &str)],
+    previous_refresh_token: Option<String>,
+) -> Result<OAuthTokens> {
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(native_tls::TlsConnector::new()?))
+        .timeout(Duration::from_secs(30))
+        .build();
+
+    let response: TokenResponse = match agent.post(&provider.token_url).send_form(form) {
+        Ok(res) => res.into_json()?,
+        Err(ureq::Error::Status(code, res)) => {
+            let body = res.into_string().unwrap_or_default();
+            warn!("Token endpoint returned {}: {}", code, body);
+            // Revoked or expired grants are not retried, the account must be authorized again
+            if code == 400 || code == 401 {
+                return Err(ConnectionError::AuthFailed(format!(
+                    "token request rejected ({}): {}",
+                    code, body
+                ))
+                .into());
+            }
+            return Err(anyhow!("token request failed ({}): {}", code, body));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(OAuthTokens {
+        access_token: response.access_token,
+        refresh_token: response.refresh_token.or(previous_refresh_token),
+        expires_at: now() + response.expires_in.unwrap_or(3600),
+    })
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::SocketAddr};
+
+    use super::*;
+
+    /// Answer one request on a local token endpoint
+    /// Returns the token url and a handle resolving to the received form body
+    fn mock_token_endpoint(
+        status: u16,
+        body: &'static str,
+    ) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/token", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = len.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut form = vec![0; content_length];
+            reader.read_exact(&mut form).unwrap();
+
+            write!(
+                stream,
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8(form).unwrap()
+        });
+
+        (url, handle)
+    }
+
+    fn provider(token_url: &str) -> OAuthProvider {
+        OAuthProvider {
+            auth_url: "https://auth.example.com/authorize".to_string(),
+            token_url: token_url.to_string(),
+            scopes: vec!["mail".to_string()],
+            client_id: "client".to_string(),
+            client_secret: None,
+        }
+    }
+
+    fn tokens(expires_in: i64) -> OAuthTokens {
+        OAuthTokens {
+            access_token: "old-access".to_string(),
+            refresh_token: Some("old-refresh".to_string()),
+            expires_at: now() + expires_in,
+        }
+    }
+
+    #[test]
+    fn exchange_sends_the_code_and_verifier() {
+        let (url, endpoint) = mock_token_endpoint(
+            200,
+            r#"{"access_token":"access","refresh_token":"refresh","expires_in":600}"#,
+        );
+
+        let tokens = exchange(
+            &provider(&url),
+            "the-code",
+            "http://127.0.0.1:1",
+            "verifier",
+        )
+        .unwrap();
+
+        let form = endpoint.join().unwrap();
+        assert!(form.contains("grant_type=authorization_code"));
+        assert!(form.contains("code=the-code"));
+        assert!(form.contains("code_verifier=verifier"));
+        assert!(form.contains("client_id=client"));
+        assert_eq!(tokens.access_token, "access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("refresh"));
+        assert!((tokens.expires_at - now() - 600).abs() <= 1);
+    }
+
+    #[test]
+    fn refresh_keeps_the_refresh_token_when_omitted() {
+        let (url, endpoint) = mock_token_endpoint(200, r#"{"access_token":"new-access"}"#);
+
+        let refreshed = refresh(&provider(&url), &tokens(0)).unwrap();
+
+        let form = endpoint.join().unwrap();
+        assert!(form.contains("grant_type=refresh_token"));
+        assert!(form.contains("refresh_token=old-refresh"));
+        assert_eq!(refreshed.access_token, "new-access");
+        assert_eq!(refreshed.refresh_token.as_deref(), Some("old-refresh"));
+        // Providers omitting expires_in get the default lifetime
+        assert!((refreshed.expires_at - now() - 3600).abs() <= 1);
+    }
+
+    #[test]
+    fn refresh_without_refresh_token_fails() {
+        let tokens = OAuthTokens {
+            refresh_token: None,
+            ..tokens(0)
+        };
+        assert!(refresh(&provider("http://127.0.0.1:1/token"), &tokens).is_err());
+    }
+
+    #[test]
+    fn request_tokens_reports_server_errors() {
+        let (url, endpoint) = mock_token_endpoint(500, r#"{"error":"server_error"}"#);
+
+        let result = request_tokens(&provider(&url), &[("grant_type", "refresh_token")], None);
+
+        endpoint.join().unwrap();
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("500"), "{}", error);
+    }
+
+    #[test]
+    fn rejected_grants_are_auth_failures() {
+        for status in [400, 401] {
+            let (url, endpoint) = mock_token_endpoint(status, r#"{"error":"invalid_grant"}"#);
+
+            let result = request_tokens(&provider(&url), &[("grant_type", "refresh_token")], None);
+
+            endpoint.join().unwrap();
+            let error = result.unwrap_err();
+            assert!(
+                matches!(
+                    error.downcast_ref::<ConnectionError>(),
+                    Some(ConnectionError::AuthFailed(message)) if message.contains("invalid_grant")
+                ),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn valid_tokens_are_not_refreshed() {
+        // The endpoint is never contacted, a request would fail to connect
+        let refreshed = fresh_tokens(&provider("http://127.0.0.1:1/token"), &tokens(3600));
+        assert!(refreshed.unwrap().is_none());
+    }
+
+    #[test]
+    fn tokens_about_to_expire_are_refreshed() {
+        let (url, endpoint) =
+            mock_token_endpoint(200, r#"{"access_token":"new-access","expires_in":3600}"#);
+
+        let refreshed = fresh_tokens(&provider(&url), &tokens(EXPIRY_MARGIN - 1)).unwrap();
+
+        endpoint.join().unwrap();
+        assert_eq!(refreshed.unwrap().access_token, "new-access");
+    }
+
+    /// Play the browser: wait for the authorization in the background
+    /// and follow the redirect of the authorization server
+    fn authorize(pending: PendingAuthorization, query: &str) -> Result<OAuthTokens> {
+        authorize_after(pending, query, |_| {})
+    }
+
+    /// Like `authorize`, `before` connects to the redirect listener first
+    fn authorize_after(
+        pending: PendingAuthorization,
+        query: &str,
+        before: impl FnOnce(SocketAddr),
+    ) -> Result<OAuthTokens> {
+        let addr = Url::parse(&pending.redirect_uri)
+            .unwrap()
+            .socket_addrs(|| None)
+            .unwrap()[0];
+        let query = query.replace("{state}", &pending.state);
+        let waiting = thread::spawn(move || pending.wait(Duration::from_secs(10)));
+
+        before(addr);
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET /?{} HTTP/1.1\r\nHost: {}\r\n\r\n", query, addr).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        waiting.join().unwrap()
+    }
+
+    #[test]
+    fn authorization_flow_exchanges_the_redirect_code() {
+        let (url, endpoint) = mock_token_endpoint(
+            200,
+            r#"{"access_token":"access","refresh_token":"refresh","expires_in":3600}"#,
+        );
+        let pending = PendingAuthorization::new(provider(&url), Some("user@example.com")).unwrap();
+        assert!(pending.url.contains("code_challenge_method=S256"));
+        assert!(pending.url.contains("login_hint=user%40example.com"));
+
+        let tokens = authorize(pending, "code=the-code&state={state}").unwrap();
+
+        let form = endpoint.join().unwrap();
+        assert!(form.contains("code=the-code"));
+        assert_eq!(tokens.access_token, "access");
+    }
+
+    #[test]
+    fn authorization_flow_rejects_a_state_mismatch() {
+        let pending =
+            PendingAuthorization::new(provider("http://127.0.0.1:1/token"), None).unwrap();
+
+        let error = authorize(pending, "code=the-code&state=forged").unwrap_err();
+
+        assert_eq!(error.to_string(), "authorization state mismatch");
+    }
+
+    #[test]
+    fn authorization_flow_reports_a_denied_consent() {
+        let pending =
+            PendingAuthorization::new(provider("http://127.0.0.1:1/token"), None).unwrap();
+
+        let error = authorize(pending, "error=access_denied").unwrap_err();
+
+        assert_eq!(error.to_string(), "authorization denied: access_denied");
+    }
+
+    #[test]
+    fn authorization_flow_skips_idle_and_unrelated_connections() {
+        let (url, endpoint) =
+            mock_token_endpoint(200, r#"{"access_token":"access","expires_in":3600}"#);
+        let pending = PendingAuthorization::new(provider(&url), None).unwrap();
+
+        let tokens = authorize_after(pending, "code=the-code&state={state}", |addr| {
+            // A preconnect that never sends its request, kept open during the flow
+            let idle = TcpStream::connect(addr).unwrap();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_secs(8));
+                drop(idle);
+            });
+            let mut favicon = TcpStream::connect(addr).unwrap();
+            write!(
+                favicon,
+                "GET /favicon.ico HTTP/1.1\r\nHost: {}\r\n\r\n",
+                addr
+            )
+            .unwrap();
+        })
+        .unwrap();
+
+        endpoint.join().unwrap();
+        assert_eq!(tokens.access_token, "access");
+    }
+}
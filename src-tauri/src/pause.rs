@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Local, Utc};
+use log::{error, info};
+use sqlx::{Pool, Sqlite};
+use tauri::{AppHandle, Manager};
+
+use crate::{db::settings, models::PauseDuration, AppState};
+
+/// Event emitted to the frontend with the end of the pause, `null` once resumed
+pub const PAUSED_EVENT: &str = "notifications_paused";
+
+/// Longest sleep before checking the wall clock again,
+/// monotonic timers do not advance while the machine is suspended
+const RESUME_CHECK_INTERVAL: i64 = 60;
+
+/// Pause notifications for the given duration
+/// Returns the unix timestamp when notifications resume
+pub async fn pause(app: &AppHandle, pool: &Pool<Sqlite>, duration: PauseDuration) -> Result<i64> {
+    let until = duration.until(Local::now()).timestamp();
+    settings::set_paused_until(Some(until), pool).await?;
+    info!("Notifications paused until {}", until);
+
+    on_change(app, Some(until));
+    schedule_resume(app.clone(), pool.clone(), until);
+
+    Ok(until)
+}
+
+/// Resume notifications right away
+pub async fn resume(app: &AppHandle, pool: &Pool<Sqlite>) -> Result<()> {
+    settings::set_paused_until(None, pool).await?;
+    info!("Notifications resumed");
+
+    on_change(app, None);
+
+    Ok(())
+}
+
+/// Restore the pause saved by a previous run, or clear it when already over
+pub async fn restore(app: &AppHandle, pool: &Pool<Sqlite>) {
+    let Ok(Some(settings)) = settings::fetch(pool).await else {
+        return;
+    };
+
+    let result = match settings.paused_until {
+        Some(until) if settings.is_paused(Utc::now().timestamp()) => {
+            on_change(app, Some(until));
+            schedule_resume(app.clone(), pool.clone(), until);
+            Ok(())
+        }
+        Some(_) => resume(app, pool).await,
+        None => Ok(()),
+    };
+
+    if let Err(e) = result {
+        error!("Failed to restore notifications pause: {:?}", e);
+    }
+}
+
+/// Resume notifications once `until` is reached,
+/// unless the pause was replaced or cancelled meanwhile
+fn schedule_resume(app: AppHandle, pool: Pool<Sqlite>, until: i64) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let remaining = until - Utc::now().timestamp();
+            if remaining <= 0 {
+                break;
+            }
+            let delay = remaining.min(RESUME_CHECK_INTERVAL) as u64;
+            tokio::time::sleep(Duration::from_secs(delay)).await;
+        }
+
+        let current = settings::fetch(&pool)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|s| s.paused_until);
+        if current != Some(until) {
+            return;
+        }
+
+        if let Err(e) = resume(&app, &pool).await {
+            error!("Failed to resume notifications: {:?}", e);
+        }
+    });
+}
+
+fn on_change(app: &AppHandle, until: Option<i64>) {
+    app.state::<AppState>().tray.set_paused(until);
+    if let Err(e) = app.emit_all(PAUSED_EVENT, until) {
+        error!("Failed to emit {}: {}", PAUSED_EVENT, e);
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Weekday};
+use log::warn;
+
+use crate::models::Settings;
+
+/// Every day of the week, see `QuietHours::days`
+pub const ALL_DAYS: i64 = 0b111_1111;
+
+/// Period of the day during which notifications are silent
+///
+/// Times are in the local time zone. When `end` is before `start` the range
+/// is overnight and ends the next day, when they are equal it lasts the whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Days on which the range starts, Monday is bit 0 and Sunday bit 6
+    pub days: i64,
+}
+
+impl QuietHours {
+    /// Quiet hours configured in the settings, `None` when they are disabled
+    pub fn from_settings(settings: &Settings) -> Result<Option<Self>> {
+        if !settings.quiet_hours.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            start: parse_time(settings.quiet_start.as_deref().unwrap_or_default())?,
+            end: parse_time(settings.quiet_end.as_deref().unwrap_or_default())?,
+            days: settings.quiet_days.unwrap_or(ALL_DAYS),
+        }))
+    }
+
+    /// Whether `now` falls in a quiet period
+    pub fn contains<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        let yesterday = today.pred();
+
+        if self.start == self.end {
+            return self.starts_on(today);
+        }
+
+        if self.start < self.end {
+            return self.starts_on(today) && self.start <= time && time < self.end;
+        }
+
+        // Overnight: the evening part belongs to today, the morning part to yesterday
+        (self.starts_on(today) && time >= self.start)
+            || (self.starts_on(yesterday) && time < self.end)
+    }
+
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days & (1 << day.num_days_from_monday()) != 0
+    }
+}
+
+/// Whether quiet hours are on right now, invalid settings are logged and ignored
+pub fn is_quiet(settings: &Settings) -> bool {
+    match QuietHours::from_settings(settings) {
+        Ok(Some(quiet_hours)) => quiet_hours.contains(&Local::now()),
+        Ok(None) => false,
+        Err(e) => {
+            warn!("Ignoring quiet hours: {}", e);
+            false
+        }
+    }
+}
+
+/// Parse a `HH:MM` time
+pub fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| anyhow!("invalid time {:?}, expected HH:MM", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    const MONDAY: i64 = 1;
+    const TUESDAY: i64 = 1 << 1;
+    const SUNDAY: i64 = 1 << 6;
+
+    fn quiet_hours(start: &str, end: &str, days: i64) -> QuietHours {
+        QuietHours {
+            start: parse_time(start).unwrap(),
+            end: parse_time(end).unwrap(),
+            days,
+        }
+    }
+
+    /// 2024-06-10 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn daytime_range() {
+        let quiet = quiet_hours("12:00", "14:00", ALL_DAYS);
+
+        assert!(!quiet.contains(&at(10, 11, 59)));
+        assert!(quiet.contains(&at(10, 12, 0)));
+        assert!(quiet.contains(&at(10, 13, 59)));
+        assert!(!quiet.contains(&at(10, 14, 0)));
+    }
+
+    #[test]
+    fn overnight_range_on_both_sides_of_midnight() {
+        let quiet = quiet_hours("22:00", "07:00", ALL_DAYS);
+
+        assert!(!quiet.contains(&at(10, 21, 59)));
+        assert!(quiet.contains(&at(10, 22, 0)));
+        assert!(quiet.contains(&at(10, 23, 59)));
+        assert!(quiet.contains(&at(11, 0, 0)));
+        assert!(quiet.contains(&at(11, 6, 59)));
+        assert!(!quiet.contains(&at(11, 7, 0)));
+        assert!(!quiet.contains(&at(11, 12, 0)));
+    }
+
+    #[test]
+    fn equal_start_and_end_last_the_whole_day() {
+        let quiet = quiet_hours("08:00", "08:00", MONDAY);
+
+        assert!(quiet.contains(&at(10, 0, 0)));
+        assert!(quiet.contains(&at(10, 8, 0)));
+        assert!(quiet.contains(&at(10, 23, 59)));
+        assert!(!quiet.contains(&at(11, 0, 0)));
+    }
+
+    #[test]
+    fn overnight_range_belongs_to_the_day_it_starts() {
+        // Monday night only, it spills over into Tuesday morning which is not selected
+        let quiet = quiet_hours("22:00", "07:00", MONDAY);
+
+        assert!(quiet.contains(&at(10, 23, 0)));
+        assert!(quiet.contains(&at(11, 6, 0)));
+        assert!(!quiet.contains(&at(11, 23, 0)));
+        assert!(!quiet.contains(&at(12, 6, 0)));
+        // Sunday night is not selected, Monday morning is not quiet
+        assert!(!quiet.contains(&at(10, 6, 0)));
+    }
+
+    #[test]
+    fn days_wrap_around_the_week() {
+        let quiet = quiet_hours("22:00", "07:00", SUNDAY | TUESDAY);
+
+        // Sunday 2024-06-09 night runs into Monday morning
+        assert!(quiet.contains(&at(9, 23, 0)));
+        assert!(quiet.contains(&at(10, 6, 0)));
+        assert!(!quiet.contains(&at(10, 23, 0)));
+        assert!(quiet.contains(&at(11, 23, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        assert!(parse_time("7:30").is_ok());
+        assert!(parse_time(" 07:30 ").is_ok());
+        for value in ["", "24:00", "07:60", "0730", "noon"] {
+            assert!(parse_time(value).is_err(), "{}", value);
+        }
+    }
+}
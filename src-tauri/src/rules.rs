@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+use regex::RegexBuilder;
+
+use crate::{
+    message::MessageHeaders,
+    models::{MatchType, Rule, RuleAction, RuleField},
+};
+
+/// Upper bound of a compiled rule regex, patterns are user input
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Outcome of the rules for a batch of new messages
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verdict {
+    /// Messages that should be notified, ignored ones are left out
+    pub notified: Vec<u32>,
+    /// At least one message matched an `Urgent` rule
+    pub urgent: bool,
+    /// Sound of the first matching `Sound` rule
+    pub sound_path: Option<String>,
+}
+
+impl Rule {
+    /// Check that the pattern can be used, regexes are compiled
+    pub fn validate(match_type: MatchType, pattern: &str) -> Result<()> {
+        if pattern.is_empty() {
+            return Err(anyhow!("rule pattern is empty"));
+        }
+        if match_type == MatchType::Regex {
+            compile(pattern)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the rule applies to the message of the given account
+    /// The pattern is compiled on every call, `evaluate` compiles it once per batch
+    pub fn matches(&self, account_id: i64, headers: &MessageHeaders) -> Result<bool> {
+        Ok(CompiledRule::new(self)?.matches(account_id, headers))
+    }
+}
+
+/// Pattern of a rule, ready to be matched
+#[derive(Debug)]
+enum Pattern {
+    /// Lowercase needle
+    Contains(String),
+    Regex(regex::Regex),
+}
+
+/// Rule with its pattern compiled
+#[derive(Debug)]
+pub struct CompiledRule<'r> {
+    pub rule: &'r Rule,
+    pattern: Pattern,
+}
+
+impl<'r> CompiledRule<'r> {
+    pub fn new(rule: &'r Rule) -> Result<Self> {
+        let pattern = match rule.match_type {
+            MatchType::Contains => Pattern::Contains(rule.pattern.to_lowercase()),
+            MatchType::Regex => Pattern::Regex(compile(&rule.pattern)?),
+        };
+        Ok(Self { rule, pattern })
+    }
+
+    /// Whether the rule applies to the message of the given account
+    pub fn matches(&self, account_id: i64, headers: &MessageHeaders) -> bool {
+        let rule = self.rule;
+        if !rule.enabled || rule.account_id.is_some_and(|id| id != account_id) {
+            return false;
+        }
+
+        let value = match rule.field {
+            RuleField::From => &headers.from,
+            RuleField::To => &headers.to,
+            RuleField::Subject => &headers.subject,
+            RuleField::ListId => &headers.list_id,
+        };
+
+        match &self.pattern {
+            Pattern::Contains(needle) => value.to_lowercase().contains(needle),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// Compile the patterns of the rules, rules with an invalid pattern are skipped
+pub fn compile_rules(rules: &[Rule]) -> Vec<CompiledRule<'_>> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            CompiledRule::new(rule)
+                .map_err(|e| warn!("Skipping rule {}: {}", rule.name, e))
+                .ok()
+        })
+        .collect()
+}
+
+/// First rule matching the message, `rules` must be sorted by position
+pub fn first_match<'r>(
+    rules: &[CompiledRule<'r>],
+    account_id: i64,
+    headers: &MessageHeaders,
+) -> Option<&'r Rule> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(account_id, headers))
+        .map(|compiled| compiled.rule)
+}
+
+/// Apply the rules to new messages
+/// Messages without headers or without matching rule are notified
+pub fn evaluate(
+    rules: &[Rule],
+    account_id: i64,
+    uids: &[u32],
+    messages: &[MessageHeaders],
+) -> Verdict {
+    let rules = compile_rules(rules);
+    let mut verdict = Verdict::default();
+
+    for &uid in uids {
+        let rule = messages
+            .iter()
+            .find(|m| m.uid == uid)
+            .and_then(|headers| first_match(&rules, account_id, headers));
+
+        match rule.map(|r| (r.action, r)) {
+            Some((RuleAction::Ignore, _)) => continue,
+            Some((RuleAction::Urgent, _)) => verdict.urgent = true,
+            Some((RuleAction::Sound, rule)) => {
+                if verdict.sound_path.is_none() {
+                    verdict.sound_path = rule.sound_path.clone();
+                }
+            }
+            Some((RuleAction::Notify, _)) | None => {}
+        }
+        verdict.notified.push(uid);
+    }
+
+    verdict
+}
+
+/// Messages matched by an `Urgent` rule, they get through a pause
+pub fn urgent_messages(
+    rules: &[Rule],
+    account_id: i64,
+    uids: &[u32],
+    messages: &[MessageHeaders],
+) -> Vec<u32> {
+    let rules = compile_rules(rules);
+    uids.iter()
+        .filter(|&&uid| {
+            messages
+                .iter()
+                .find(|m| m.uid == uid)
+                .and_then(|headers| first_match(&rules, account_id, headers))
+                .is_some_and(|rule| rule.action == RuleAction::Urgent)
+        })
+        .copied()
+        .collect()
+}
+
+fn compile(pattern: &str) -> Result<regex::Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| anyhow!("invalid regex: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i64, field: RuleField, pattern: &str, action: RuleAction) -> Rule {
+        Rule {
+            id,
+            account_id: None,
+            name: format!("rule {}", id),
+            field,
+            match_type: MatchType::Contains,
+            pattern: pattern.to_string(),
+            action,
+            sound_path: None,
+            position: id,
+            enabled: true,
+        }
+    }
+
+    fn message(uid: u32, from: &str, subject: &str) -> MessageHeaders {
+        MessageHeaders {
+            uid,
+            from: from.to_string(),
+            subject: subject.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_rule_by_position_wins() {
+        let rules = vec![
+            rule(1, RuleField::Subject, "invoice", RuleAction::Urgent),
+            rule(2, RuleField::From, "billing@", RuleAction::Ignore),
+        ];
+        let messages = [message(10, "billing@example.com", "Your invoice")];
+
+        let verdict = evaluate(&rules, 1, &[10], &messages);
+        assert_eq!(verdict.notified, vec![10]);
+        assert!(verdict.urgent);
+
+        let reversed: Vec<_> = rules.into_iter().rev().collect();
+        let verdict = evaluate(&reversed, 1, &[10], &messages);
+        assert!(verdict.notified.is_empty());
+        assert!(!verdict.urgent);
+    }
+
+    #[test]
+    fn rules_are_scoped_to_their_account() {
+        let mut scoped = rule(1, RuleField::From, "news@", RuleAction::Ignore);
+        scoped.account_id = Some(2);
+        let messages = [message(10, "news@example.com", "Weekly")];
+
+        assert_eq!(
+            evaluate(&[scoped.clone()], 1, &[10], &messages).notified,
+            vec![10]
+        );
+        assert!(evaluate(&[scoped], 2, &[10], &messages).notified.is_empty());
+    }
+
+    #[test]
+    fn disabled_rules_are_ignored() {
+        let mut disabled = rule(1, RuleField::From, "news@", RuleAction::Ignore);
+        disabled.enabled = false;
+        let messages = [message(10, "news@example.com", "Weekly")];
+
+        assert_eq!(
+            evaluate(&[disabled], 1, &[10], &messages).notified,
+            vec![10]
+        );
+    }
+
+    #[test]
+    fn verdict_combines_actions() {
+        let mut sound = rule(3, RuleField::Subject, "build", RuleAction::Sound);
+        sound.sound_path = Some("/sounds/build.wav".to_string());
+        let mut other_sound = rule(4, RuleField::Subject, "deploy", RuleAction::Sound);
+        other_sound.sound_path = Some("/sounds/deploy.wav".to_string());
+        let rules = vec![
+            rule(1, RuleField::From, "noreply@", RuleAction::Ignore),
+            rule(2, RuleField::From, "boss@", RuleAction::Urgent),
+            sound,
+            other_sound,
+        ];
+        let messages = [
+            message(1, "noreply@example.com", "Newsletter"),
+            message(2, "boss@example.com", "Call me"),
+            message(3, "ci@example.com", "Build failed"),
+            message(4, "ci@example.com", "Deploy done"),
+        ];
+
+        let verdict = evaluate(&rules, 1, &[1, 2, 3, 4], &messages);
+
+        assert_eq!(
+            verdict,
+            Verdict {
+                notified: vec![2, 3, 4],
+                urgent: true,
+                sound_path: Some("/sounds/build.wav".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn unmatched_messages_are_notified() {
+        let rules = vec![rule(1, RuleField::From, "noreply@", RuleAction::Ignore)];
+        // Message 2 has no headers, e.g. the fetch failed
+        let messages = [message(1, "jane@example.com", "Hello")];
+
+        let verdict = evaluate(&rules, 1, &[1, 2], &messages);
+
+        assert_eq!(
+            verdict,
+            Verdict {
+                notified: vec![1, 2],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn urgent_messages_only_keeps_urgent_matches() {
+        let rules = vec![
+            rule(1, RuleField::From, "boss@", RuleAction::Urgent),
+            rule(2, RuleField::Subject, "call", RuleAction::Ignore),
+            rule(3, RuleField::Subject, "outage", RuleAction::Urgent),
+        ];
+        let messages = [
+            message(1, "boss@example.com", "Call me"),
+            message(2, "jane@example.com", "Call me"),
+            message(3, "ops@example.com", "Outage"),
+            message(4, "jane@example.com", "Lunch?"),
+        ];
+
+        assert_eq!(
+            urgent_messages(&rules, 1, &[1, 2, 3, 4, 5], &messages),
+            vec![1, 3]
+        );
+    }
+
+    #[test]
+    fn regex_rules_match_case_insensitively() {
+        let mut regex = rule(
+            1,
+            RuleField::Subject,
+            r"^\[(alert|page)\]",
+            RuleAction::Urgent,
+        );
+        regex.match_type = MatchType::Regex;
+
+        assert!(regex
+            .matches(1, &message(1, "", "[PAGE] disk full"))
+            .unwrap());
+        assert!(!regex
+            .matches(1, &message(1, "", "Re: [page] disk full"))
+            .unwrap());
+    }
+
+    #[test]
+    fn invalid_regex_rules_are_skipped() {
+        let mut invalid = rule(1, RuleField::Subject, "(", RuleAction::Ignore);
+        invalid.match_type = MatchType::Regex;
+        let rules = vec![
+            invalid,
+            rule(2, RuleField::Subject, "hello", RuleAction::Urgent),
+        ];
+
+        assert!(rules[0].matches(1, &message(1, "", "hello")).is_err());
+        assert_eq!(compile_rules(&rules).len(), 1);
+        assert!(evaluate(&rules, 1, &[1], &[message(1, "", "hello")]).urgent);
+        assert!(Rule::validate(MatchType::Regex, "(").is_err());
+    }
+}
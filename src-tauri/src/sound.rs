@@ -0,0 +1,121 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    thread,
+};
+
+use anyhow::Result;
+use flume::{Receiver, Sender};
+use log::{error, info, warn};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+
+/// Bundled notification sound, declared in the `tauri.conf.json` resources
+pub const BUNDLED_SOUND: &str = "sounds/message.mp3";
+
+#[derive(Debug)]
+struct PlayRequest {
+    path: PathBuf,
+    volume: f32,
+}
+
+/// Plays notification sounds on a dedicated thread
+///
+/// Opening the output device, decoding and playing never block the caller,
+/// requests are queued and the sounds are mixed by rodio in the background.
+#[derive(Debug, Clone)]
+pub struct SoundPlayer {
+    tx: Sender<PlayRequest>,
+    /// Path of the bundled sound, resolved once the app is set up
+    bundled: Arc<OnceLock<PathBuf>>,
+}
+
+impl SoundPlayer {
+    pub fn new() -> Self {
+        let (tx, rx) = flume::unbounded();
+
+        let spawned = thread::Builder::new()
+            .name("sound".to_string())
+            .spawn(move || SoundPlayer::run(rx));
+        if let Err(e) = spawned {
+            error!("Failed to spawn sound thread: {}", e);
+        }
+
+        Self {
+            tx,
+            bundled: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Set the resolved path of the bundled sound
+    pub fn set_bundled_sound(&self, path: PathBuf) {
+        let _ = self.bundled.set(path);
+    }
+
+    /// Queue a sound, `custom` replaces the bundled sound when set
+    /// `volume` goes from 0.0 to 1.0
+    pub fn play(&self, custom: Option<&str>, volume: f64) {
+        let path = match custom {
+            Some(path) => PathBuf::from(path),
+            None => match self.bundled.get() {
+                Some(path) => path.clone(),
+                None => {
+                    warn!("Bundled sound not resolved yet");
+                    return;
+                }
+            },
+        };
+
+        let request = PlayRequest {
+            path,
+            volume: volume.clamp(0.0, 1.0) as f32,
+        };
+        if self.tx.send(request).is_err() {
+            error!("Sound thread is not running");
+        }
+    }
+
+    fn run(rx: Receiver<PlayRequest>) {
+        // The output stream is not `Send`, it lives and dies with this thread.
+        // It is opened on the first request, so machines without audio devices
+        // only log an error when a sound is actually requested.
+        let mut output: Option<(OutputStream, OutputStreamHandle)> = None;
+
+        while let Ok(request) = rx.recv() {
+            if output.is_none() {
+                match OutputStream::try_default() {
+                    Ok(stream) => output = Some(stream),
+                    Err(e) => {
+                        error!("Failed to open audio output: {}", e);
+                        continue;
+                    }
+                }
+            }
+
+            if let Some((_, handle)) = &output {
+                if let Err(e) = play(handle, &request.path, request.volume) {
+                    warn!("Failed to play {}: {}", request.path.display(), e);
+                }
+            }
+        }
+
+        info!("Sound thread stopped");
+    }
+}
+
+impl Default for SoundPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn play(handle: &OutputStreamHandle, path: &Path, volume: f32) -> Result<()> {
+    let source = Decoder::new(BufReader::new(File::open(path)?))?;
+    let sink = Sink::try_new(handle)?;
+    sink.set_volume(volume);
+    sink.append(source);
+    // Keep playing once the sink goes out of scope
+    sink.detach();
+    Ok(())
+}
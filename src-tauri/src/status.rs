@@ -0,0 +1,91 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+use chrono::Utc;
+use log::error;
+use tauri::{AppHandle, Manager};
+
+use crate::models::{AccountStatus, StatusUpdate};
+
+/// Event emitted to the frontend with the `AccountStatus` of a mailbox, on every transition
+pub const ACCOUNT_STATUS_EVENT: &str = "account_status";
+
+/// Event emitted to the frontend with the id of an account whose statuses were removed
+pub const ACCOUNT_STATUS_REMOVED_EVENT: &str = "account_status_removed";
+
+/// Connection status of every watched mailbox, keyed by account id and mailbox
+///
+/// Like `Tray`, it is cloned into the notifier task before the app is built,
+/// events are only emitted once `set_app` is called.
+#[derive(Debug, Clone, Default)]
+pub struct Statuses {
+    app: Arc<OnceLock<AppHandle>>,
+    statuses: Arc<Mutex<BTreeMap<(i64, String), AccountStatus>>>,
+}
+
+impl Statuses {
+    pub fn set_app(&self, app: AppHandle) {
+        let _ = self.app.set(app);
+    }
+
+    /// Apply a transition reported by a session and notify the frontend
+    pub fn update(&self, account_id: i64, mailbox: &str, update: StatusUpdate) {
+        let now = Utc::now().timestamp();
+        let Some(status) = self.lock().map(|mut statuses| {
+            let status = statuses
+                .entry((account_id, mailbox.to_string()))
+                .or_insert_with(|| AccountStatus::new(account_id, mailbox, now));
+            status.apply(update, now);
+            status.clone()
+        }) else {
+            return;
+        };
+
+        if let Some(app) = self.app.get() {
+            if let Err(e) = app.emit_all(ACCOUNT_STATUS_EVENT, status) {
+                error!("Failed to emit {}: {}", ACCOUNT_STATUS_EVENT, e);
+            }
+        }
+    }
+
+    /// Record a check of a mailbox whose state did not change
+    /// Not emitted, the frontend reads `last_checked` when it fetches the statuses
+    pub fn checked(&self, account_id: i64, mailbox: &str) {
+        let now = Utc::now().timestamp();
+        if let Some(mut statuses) = self.lock() {
+            if let Some(status) = statuses.get_mut(&(account_id, mailbox.to_string())) {
+                status.last_checked = Some(now);
+            }
+        }
+    }
+
+    /// Forget the statuses of an account and notify the frontend,
+    /// its sessions report again once restarted
+    pub fn remove_account(&self, account_id: i64) {
+        if let Some(mut statuses) = self.lock() {
+            statuses.retain(|(id, _), _| *id != account_id);
+        }
+
+        if let Some(app) = self.app.get() {
+            if let Err(e) = app.emit_all(ACCOUNT_STATUS_REMOVED_EVENT, account_id) {
+                error!("Failed to emit {}: {}", ACCOUNT_STATUS_REMOVED_EVENT, e);
+            }
+        }
+    }
+
+    /// Status of every mailbox, in account id order
+    pub fn all(&self) -> Vec<AccountStatus> {
+        self.lock()
+            .map(|statuses| statuses.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn lock(&self) -> Option<MutexGuard<'_, BTreeMap<(i64, String), AccountStatus>>> {
+        self.statuses
+            .lock()
+            .map_err(|e| error!("Account statuses poisoned: {}", e))
+            .ok()
+    }
+}
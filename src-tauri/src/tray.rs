@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use chrono::{Local, TimeZone};
+use log::error;
+use tauri::{
+    AppHandle, CustomMenuItem, Icon, Manager, PhysicalPosition, PhysicalSize, SystemTray,
+    SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem, SystemTraySubmenu, Window,
+};
+
+use crate::{
+    models::PauseDuration,
+    pause,
+    tray_icon::{parse_color, Rgba, TrayIcon, BASE_ICON},
+    unread::UnreadCounts,
+    AppState,
+};
+
+const QUIT: &str = "quit";
+const PAUSE_30_MINUTES: &str = "pause_30_minutes";
+const PAUSE_1_HOUR: &str = "pause_1_hour";
+const PAUSE_UNTIL_TOMORROW: &str = "pause_until_tomorrow";
+const RESUME: &str = "resume";
+/// Prefix of the account entries ids, followed by the account id
+const ACCOUNT_PREFIX: &str = "account_";
+
+const TOOLTIP: &str = "MailWatch";
+
+#[derive(Debug, Default)]
+struct TrayState {
+    unread: UnreadCounts,
+    /// Unix timestamp until which notifications are paused
+    paused_until: Option<i64>,
+    /// Mailboxes whose session is disconnected, by account id
+    disconnected: BTreeSet<(i64, String)>,
+    /// Color of the account with the most recent mail
+    tint: Option<[u8; 3]>,
+    /// Icon currently shown, `None` until the first refresh
+    icon: Option<TrayIcon>,
+}
+
+impl TrayState {
+    fn icon(&self) -> TrayIcon {
+        TrayIcon::new(
+            self.unread.total(),
+            self.tint,
+            !self.disconnected.is_empty(),
+        )
+    }
+}
+
+/// Tray icon, menu and tooltip, rebuilt whenever the unread counts,
+/// the connection of the sessions or the pause change
+///
+/// Cloned into the notifier task, which runs before the app is built,
+/// the menu is only applied once `set_app` is called.
+#[derive(Debug, Clone, Default)]
+pub struct Tray {
+    app: Arc<OnceLock<AppHandle>>,
+    state: Arc<Mutex<TrayState>>,
+}
+
+impl Tray {
+    pub fn build() -> SystemTray {
+        let state = TrayState::default();
+        SystemTray::new()
+            .with_menu(menu(&state))
+            .with_tooltip(&tooltip(&state))
+    }
+
+    /// Set the app handle and apply the current state
+    pub fn set_app(&self, app: AppHandle) {
+        let _ = self.app.set(app);
+        self.refresh();
+    }
+
+    /// Update the unseen count of a watched mailbox, the tint is reset once everything is read
+    pub fn set_unseen(&self, account_id: i64, name: &str, mailbox: &str, count: u32) {
+        let changed = self.lock().is_some_and(|mut state| {
+            let changed = state.unread.set(account_id, name, mailbox, count);
+            if state.unread.total() == 0 {
+                state.tint = None;
+            }
+            changed
+        });
+        if changed {
+            self.refresh();
+        }
+    }
+
+    /// Track sessions unable to connect, they turn the icon into its error variant
+    pub fn set_connected(&self, account_id: i64, mailbox: &str, connected: bool) {
+        let changed = self.lock().is_some_and(|mut state| {
+            let key = (account_id, mailbox.to_string());
+            match connected {
+                true => state.disconnected.remove(&key),
+                false => state.disconnected.insert(key),
+            }
+        });
+        if changed {
+            self.refresh();
+        }
+    }
+
+    /// Tint the icon with the color of the account that received the latest mail
+    pub fn set_latest_mail(&self, color: &str) {
+        let tint = parse_color(color);
+        let changed = self.lock().is_some_and(|mut state| {
+            let changed = state.tint != tint;
+            state.tint = tint;
+            changed
+        });
+        if changed {
+            self.refresh();
+        }
+    }
+
+    /// Remove the entry of an account, its sessions report again once restarted
+    pub fn remove_account(&self, account_id: i64) {
+        let changed = self.lock().is_some_and(|mut state| {
+            let before = state.disconnected.len();
+            state.disconnected.retain(|(id, _)| *id != account_id);
+            state.unread.remove(account_id) || state.disconnected.len() != before
+        });
+        if changed {
+            self.refresh();
+        }
+    }
+
+    /// Reflect the pause state in the tray menu
+    pub fn set_paused(&self, until: Option<i64>) {
+        if let Some(mut state) = self.lock() {
+            state.paused_until = until;
+        }
+        self.refresh();
+    }
+
+    fn refresh(&self) {
+        let (Some(app), Some(mut state)) = (self.app.get(), self.lock()) else {
+            return;
+        };
+
+        let tray = app.tray_handle();
+        if let Err(e) = tray
+            .set_menu(menu(&state))
+            .and_then(|_| tray.set_tooltip(&tooltip(&state)))
+        {
+            error!("Failed to update tray menu: {}", e);
+        }
+
+        let icon = state.icon();
+        if state.icon != Some(icon) {
+            state.icon = Some(icon);
+            set_icon(app, icon);
+        }
+    }
+
+    fn lock(&self) -> Option<std::sync::MutexGuard<'_, TrayState>> {
+        self.state
+            .lock()
+            .map_err(|e| error!("Tray state poisoned: {}", e))
+            .ok()
+    }
+}
+
+fn menu(state: &TrayState) -> SystemTrayMenu {
+    let mut tray_menu = SystemTrayMenu::new();
+
+    let accounts = state.unread.accounts();
+    for (id, name, count) in &accounts {
+        let mut title = match count {
+            0 => name.to_string(),
+            n => format!("{} ({})", name, n),
+        };
+        if state
+            .disconnected
+            .iter()
+            .any(|(account_id, _)| account_id == id)
+        {
+            title.push_str(" - disconnected");
+        }
+        tray_menu = tray_menu.add_item(CustomMenuItem::new(
+            format!("{}{}", ACCOUNT_PREFIX, id),
+            title,
+        ));
+    }
+    if !accounts.is_empty() {
+        tray_menu = tray_menu.add_native_item(SystemTrayMenuItem::Separator);
+    }
+
+    let pause_menu = SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(PAUSE_30_MINUTES, "For 30 minutes"))
+        .add_item(CustomMenuItem::new(PAUSE_1_HOUR, "For 1 hour"))
+        .add_item(CustomMenuItem::new(PAUSE_UNTIL_TOMORROW, "Until tomorrow"));
+
+    let resume = match state
+        .paused_until
+        .and_then(|until| Local.timestamp_opt(until, 0).single())
+    {
+        Some(until) => CustomMenuItem::new(
+            RESUME,
+            format!(
+                "Resume notifications (paused until {})",
+                until.format("%H:%M")
+            ),
+        ),
+        None => CustomMenuItem::new(RESUME, "Resume notifications").disabled(),
+    };
+
+    let quit = CustomMenuItem::new(QUIT.to_string(), "Quit").accelerator("Cmd+Q");
+    tray_menu
+        .add_submenu(SystemTraySubmenu::new("Pause notifications", pause_menu))
+        .add_item(resume)
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(quit)
+}
+
+/// Render the icon from the base image, plain icons stay templates on macOS
+fn set_icon(app: &AppHandle, icon: TrayIcon) {
+    let tray = app.tray_handle();
+    let result = Rgba::decode_png(BASE_ICON).and_then(|base| {
+        let rendered = icon.render(&base);
+        tray.set_icon(Icon::Rgba {
+            rgba: rendered.pixels,
+            width: rendered.width,
+            height: rendered.height,
+        })?;
+        #[cfg(target_os = "macos")]
+        tray.set_icon_as_template(icon.is_plain())?;
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        error!("Failed to update tray icon: {:?}", e);
+    }
+}
+
+fn tooltip(state: &TrayState) -> String {
+    match state.unread.total() {
+        0 => TOOLTIP.to_string(),
+        1 => format!("{} - 1 unread message", TOOLTIP),
+        n => format!("{} - {} unread messages", TOOLTIP, n),
+    }
+}
+
+pub fn on_event(app: &AppHandle, e: SystemTrayEvent) {
+    match e {
+        tauri::SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+            QUIT => std::process::exit(0),
+            PAUSE_30_MINUTES => pause_notifications(app, Some(PauseDuration::ThirtyMinutes)),
+            PAUSE_1_HOUR => pause_notifications(app, Some(PauseDuration::OneHour)),
+            PAUSE_UNTIL_TOMORROW => pause_notifications(app, Some(PauseDuration::UntilTomorrow)),
+            RESUME => pause_notifications(app, None),
+            id if id.starts_with(ACCOUNT_PREFIX) => show_window(app),
+            _ => {}
+        },
+        // On left click toggle the window visibility
+        // Show menu on left click should be disabled on tauri config
+        tauri::SystemTrayEvent::LeftClick {
+            tray_id: _,
+            position,
+            size,
+            ..
+        } => {
+            toggle_window_visibility(&app.get_window("main").unwrap(), position, size);
+        }
+        _ => {}
+    };
+}
+
+/// Pause for the given duration, or resume when `None`
+fn pause_notifications(app: &AppHandle, duration: Option<PauseDuration>) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let pool = app.state::<AppState>().pool.clone();
+        let result = match duration {
+            Some(duration) => pause::pause(&app, &pool, duration).await.map(|_| ()),
+            None => pause::resume(&app, &pool).await,
+        };
+        if let Err(e) = result {
+            error!("Failed to change notifications pause: {:?}", e);
+        }
+    });
+}
+
+fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.show().and_then(|_| window.set_focus());
+    }
+}
+
+fn toggle_window_visibility(
+    window: &Window,
+    position: PhysicalPosition<f64>,
+    size: PhysicalSize<f64>,
+) {
+    match window.is_visible().unwrap() {
+        true => window.hide().unwrap(),
+        false => {
+            let window_size = window.outer_size().unwrap();
+            let physical_pos = PhysicalPosition {
+                x: position.x as i32 + (size.width as i32 / 2) - (window_size.width as i32 / 2),
+                y: position.y as i32 - window_size.height as i32,
+            };
+            let _ = window.set_position(tauri::Position::Physical(physical_pos));
+            window.show().unwrap();
+            window.set_focus().unwrap();
+        }
+    };
+}
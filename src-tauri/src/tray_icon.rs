@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Result};
+use png::{ColorType, Decoder, Transformations};
+
+/// Base tray icon, a black template image
+pub const BASE_ICON: &[u8] = include_bytes!("../icons/tray.png");
+
+/// Badge with the unread count
+const BADGE_COLOR: [u8; 3] = [219, 39, 119];
+
+/// Badge of the error variant
+const ERROR_COLOR: [u8; 3] = [220, 38, 38];
+
+const TEXT_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Badge diameter, in pixels
+const BADGE_SIZE: f32 = 13.0;
+
+/// Transparent ring separating the badge from the icon
+const BADGE_GAP: f32 = 1.0;
+
+/// Opacity of the icon in the error variant
+const ERROR_OPACITY: f32 = 0.5;
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// Account colors, as `tailwindcss` 500 shades
+const COLORS: [(&str, [u8; 3]); 14] = [
+    ("sky", [14, 165, 233]),
+    ("red", [239, 68, 68]),
+    ("orange", [249, 115, 22]),
+    ("amber", [245, 158, 11]),
+    ("yellow", [234, 179, 8]),
+    ("lime", [132, 204, 22]),
+    ("emerald", [16, 185, 129]),
+    ("teal", [20, 184, 166]),
+    ("cyan", [6, 182, 212]),
+    ("blue", [59, 130, 246]),
+    ("indigo", [99, 102, 241]),
+    ("violet", [139, 92, 246]),
+    ("purple", [168, 85, 247]),
+    ("pink", [236, 72, 153]),
+];
+
+/// Decoded image, 8 bits RGBA
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rgba {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Rgba {
+    /// Decode a PNG image
+    pub fn decode_png(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        buf.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            ColorType::Rgba => buf,
+            ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            ColorType::Indexed => return Err(anyhow!("indexed PNG not expanded")),
+        };
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn blend(&mut self, x: usize, y: usize, color: [u8; 3], coverage: f32) {
+        let i = (y * self.width as usize + x) * 4;
+        let Some(pixel) = self.pixels.get_mut(i..i + 4) else {
+            return;
+        };
+
+        let src_alpha = coverage.clamp(0.0, 1.0);
+        let dst_alpha = pixel[3] as f32 / 255.0;
+        let alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+        if alpha <= 0.0 {
+            return;
+        }
+        for c in 0..3 {
+            let value = (color[c] as f32 * src_alpha
+                + pixel[c] as f32 * dst_alpha * (1.0 - src_alpha))
+                / alpha;
+            pixel[c] = value.round() as u8;
+        }
+        pixel[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+/// What the tray icon shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrayIcon {
+    /// Total unread count, shown in a badge
+    pub unread: u32,
+    /// Color of the account with the most recent mail
+    pub tint: Option<[u8; 3]>,
+    /// A watcher is disconnected, takes precedence over the unread badge
+    pub error: bool,
+}
+
+impl TrayIcon {
+    /// The tint marks the latest unread mail, it goes away once everything is read
+    pub fn new(unread: u32, tint: Option<[u8; 3]>, error: bool) -> Self {
+        TrayIcon {
+            unread,
+            tint: tint.filter(|_| unread > 0),
+            error,
+        }
+    }
+
+    /// Nothing to draw, the base icon can be used as a template
+    pub fn is_plain(&self) -> bool {
+        self.unread == 0 && self.tint.is_none() && !self.error
+    }
+
+    /// Draw the icon over `base`
+    pub fn render(&self, base: &Rgba) -> Rgba {
+        let mut icon = base.clone();
+
+        for pixel in icon.pixels.chunks_exact_mut(4) {
+            if let Some(tint) = self.tint {
+                pixel[..3].copy_from_slice(&tint);
+            }
+            if self.error {
+                pixel[3] = (pixel[3] as f32 * ERROR_OPACITY).round() as u8;
+            }
+        }
+
+        match (self.error, self.unread) {
+            (true, _) => draw_badge(&mut icon, ERROR_COLOR, "!"),
+            (false, 0) => {}
+            (false, n) if n > 99 => draw_badge(&mut icon, BADGE_COLOR, "99+"),
+            (false, n) => draw_badge(&mut icon, BADGE_COLOR, &n.to_string()),
+        }
+
+        icon
+    }
+}
+
+/// Parse an account color, either a known color name or `#rrggbb`
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let color = color.trim().to_lowercase();
+    if let Some((_, rgb)) = COLORS.iter().find(|(name, _)| *name == color) {
+        return Some(*rgb);
+    }
+
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Draw a round badge in the top right corner with a short label
+fn draw_badge(icon: &mut Rgba, color: [u8; 3], label: &str) {
+    let radius = BADGE_SIZE / 2.0;
+    let cx = icon.width as f32 - radius;
+    let cy = radius;
+
+    for y in 0..icon.height as usize {
+        for x in 0..icon.width as usize {
+            let dx = x as f32 + 0.5 - cx;
+            let dy = y as f32 + 0.5 - cy;
+            let distance = (dx * dx + dy * dy).sqrt();
+
+            // Clear a ring around the badge, then draw the antialiased disc
+            let gap = (radius + BADGE_GAP + 0.5 - distance).clamp(0.0, 1.0);
+            if gap > 0.0 {
+                let i = (y * icon.width as usize + x) * 4 + 3;
+                icon.pixels[i] = (icon.pixels[i] as f32 * (1.0 - gap)).round() as u8;
+            }
+            icon.blend(x, y, color, radius + 0.5 - distance);
+        }
+    }
+
+    let glyphs: Vec<&[u8; GLYPH_HEIGHT]> = label.chars().filter_map(glyph).collect();
+    let text_width = glyphs.len() * (GLYPH_WIDTH + 1) - 1;
+    let left = (cx - text_width as f32 / 2.0).round() as usize;
+    let top = (cy - GLYPH_HEIGHT as f32 / 2.0).round() as usize;
+
+    for (n, rows) in glyphs.iter().enumerate() {
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                    let x = left + n * (GLYPH_WIDTH + 1) + col;
+                    icon.blend(x, top + row, TEXT_COLOR, 1.0);
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 bitmap of the badge characters, one byte per row
+fn glyph(c: char) -> Option<&'static [u8; GLYPH_HEIGHT]> {
+    const DIGITS: [[u8; GLYPH_HEIGHT]; 10] = [
+        [0b111, 0b101, 0b101, 0b101, 0b111],
+        [0b010, 0b110, 0b010, 0b010, 0b111],
+        [0b111, 0b001, 0b111, 0b100, 0b111],
+        [0b111, 0b001, 0b111, 0b001, 0b111],
+        [0b101, 0b101, 0b111, 0b001, 0b001],
+        [0b111, 0b100, 0b111, 0b001, 0b111],
+        [0b111, 0b100, 0b111, 0b101, 0b111],
+        [0b111, 0b001, 0b010, 0b010, 0b010],
+        [0b111, 0b101, 0b111, 0b101, 0b111],
+        [0b111, 0b101, 0b111, 0b001, 0b111],
+    ];
+    const PLUS: [u8; GLYPH_HEIGHT] = [0b000, 0b010, 0b111, 0b010, 0b000];
+    const BANG: [u8; GLYPH_HEIGHT] = [0b010, 0b010, 0b010, 0b000, 0b010];
+
+    match c {
+        '0'..='9' => DIGITS.get(c as usize - '0' as usize),
+        '+' => Some(&PLUS),
+        '!' => Some(&BANG),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use png::{BitDepth, Encoder};
+
+    use super::*;
+
+    /// Compare a render with its PNG fixture
+    /// Run the tests with `UPDATE_FIXTURES=1` to write the fixtures after a design change
+    fn assert_fixture(name: &str, icon: &Rgba) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tray")
+            .join(format!("{}.png", name));
+
+        if std::env::var_os("UPDATE_FIXTURES").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let mut encoder =
+                Encoder::new(fs::File::create(&path).unwrap(), icon.width, icon.height);
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_depth(BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&icon.pixels).unwrap();
+        }
+
+        let bytes = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let fixture = Rgba::decode_png(&bytes).unwrap();
+        assert!(fixture == *icon, "{} differs from {}", name, path.display());
+    }
+
+    fn render(icon: TrayIcon) -> Rgba {
+        icon.render(&Rgba::decode_png(BASE_ICON).unwrap())
+    }
+
+    #[test]
+    fn plain_icon_is_the_base_icon() {
+        let icon = TrayIcon::default();
+        assert!(icon.is_plain());
+
+        let rendered = render(icon);
+        assert_eq!(rendered, Rgba::decode_png(BASE_ICON).unwrap());
+        assert_fixture("plain", &rendered);
+    }
+
+    #[test]
+    fn unread_badges() {
+        for unread in [1, 9, 42] {
+            let icon = TrayIcon {
+                unread,
+                ..Default::default()
+            };
+            assert!(!icon.is_plain());
+            assert_fixture(&format!("unread_{}", unread), &render(icon));
+        }
+    }
+
+    #[test]
+    fn counts_over_99_are_capped() {
+        let capped = render(TrayIcon {
+            unread: 100,
+            ..Default::default()
+        });
+        assert_fixture("unread_99_plus", &capped);
+        assert_eq!(
+            capped,
+            render(TrayIcon {
+                unread: 12_345,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn tinted_icon() {
+        assert_fixture(
+            "tinted",
+            &render(TrayIcon {
+                unread: 3,
+                tint: parse_color("emerald"),
+                error: false,
+            }),
+        );
+    }
+
+    #[test]
+    fn tint_is_cleared_once_everything_is_read() {
+        let tint = parse_color("emerald");
+        assert_fixture("tinted", &render(TrayIcon::new(3, tint, false)));
+
+        let read = TrayIcon::new(0, tint, false);
+        assert!(read.is_plain());
+        assert_fixture("plain", &render(read));
+    }
+
+    #[test]
+    fn error_badge_replaces_the_unread_count() {
+        let error = render(TrayIcon {
+            error: true,
+            ..Default::default()
+        });
+        assert_fixture("error", &error);
+        assert_eq!(
+            error,
+            render(TrayIcon {
+                unread: 5,
+                error: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parses_color_names() {
+        assert_eq!(parse_color("sky"), Some([14, 165, 233]));
+        assert_eq!(parse_color(" Pink "), Some([236, 72, 153]));
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_color("#ff8000"), Some([255, 128, 0]));
+        assert_eq!(parse_color("#00AAff"), Some([0, 170, 255]));
+    }
+
+    #[test]
+    fn rejects_bad_colors() {
+        for color in [
+            "", "magenta", "ff8000", "#fff", "#ff80001", "#gg8000", "#ff80é",
+        ] {
+            assert_eq!(parse_color(color), None, "{}", color);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+
+/// Unseen messages of an account, per watched mailbox
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct AccountUnread {
+    name: String,
+    mailboxes: BTreeMap<String, u32>,
+}
+
+/// Unseen messages reported by the watcher sessions, keyed by account id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnreadCounts {
+    accounts: BTreeMap<i64, AccountUnread>,
+}
+
+impl UnreadCounts {
+    /// Record the unseen count of a mailbox, returns true when it changed
+    pub fn set(&mut self, account_id: i64, name: &str, mailbox: &str, count: u32) -> bool {
+        let account = self.accounts.entry(account_id).or_default();
+        let renamed = account.name != name;
+        if renamed {
+            account.name = name.to_string();
+        }
+        let previous = account.mailboxes.insert(mailbox.to_string(), count);
+        renamed || previous != Some(count)
+    }
+
+    /// Forget an account, returns true when it was known
+    pub fn remove(&mut self, account_id: i64) -> bool {
+        self.accounts.remove(&account_id).is_some()
+    }
+
+    /// Name and unseen count of every account, in id order
+    pub fn accounts(&self) -> Vec<(i64, &str, u32)> {
+        self.accounts
+            .iter()
+            .map(|(id, acc)| (*id, acc.name.as_str(), acc.mailboxes.values().sum()))
+            .collect()
+    }
+
+    pub fn total(&self) -> u32 {
+        self.accounts
+            .values()
+            .flat_map(|acc| acc.mailboxes.values())
+            .sum()
+    }
+}
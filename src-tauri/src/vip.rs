@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Result};
+
+use crate::{message::MessageHeaders, models::VipSender};
+
+impl VipSender {
+    /// Normalize a VIP entry, a leading `@` or `*.` marks a domain
+    /// and is dropped, addresses and domains are lowercased
+    pub fn normalize(address: &str) -> Result<String> {
+        let address = address
+            .trim()
+            .trim_start_matches("*.")
+            .trim_start_matches('@')
+            .to_lowercase();
+
+        let valid = match address.split_once('@') {
+            Some((local, domain)) => !local.is_empty() && !domain.is_empty(),
+            None => address.contains('.'),
+        };
+        if !valid || address.chars().any(char::is_whitespace) {
+            return Err(anyhow!("invalid address or domain: {}", address));
+        }
+        Ok(address)
+    }
+
+    /// Whether mail from `address` received by the account comes from this VIP
+    /// Domain entries also match their subdomains
+    pub fn matches(&self, account_id: i64, address: &str) -> bool {
+        if self.account_id.is_some_and(|id| id != account_id) {
+            return false;
+        }
+        if self.address.contains('@') {
+            return self.address == address;
+        }
+
+        let domain = address.rsplit_once('@').map_or("", |(_, domain)| domain);
+        domain == self.address
+            || domain
+                .strip_suffix(&self.address)
+                .is_some_and(|sub| sub.ends_with('.'))
+    }
+}
+
+/// Uids of the new messages sent by a VIP
+pub fn vip_messages(
+    vips: &[VipSender],
+    account_id: i64,
+    uids: &[u32],
+    messages: &[MessageHeaders],
+) -> Vec<u32> {
+    messages
+        .iter()
+        .filter(|m| uids.contains(&m.uid))
+        .filter(|m| {
+            let address = m.sender_address();
+            vips.iter().any(|vip| vip.matches(account_id, &address))
+        })
+        .map(|m| m.uid)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vip(account_id: Option<i64>, address: &str) -> VipSender {
+        VipSender {
+            id: 1,
+            account_id,
+            address: VipSender::normalize(address).unwrap(),
+        }
+    }
+
+    fn message(uid: u32, from: &str) -> MessageHeaders {
+        MessageHeaders {
+            uid,
+            from: from.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalizes_entries() {
+        assert_eq!(
+            VipSender::normalize(" Boss@Example.COM ").unwrap(),
+            "boss@example.com"
+        );
+        assert_eq!(VipSender::normalize("@Example.com").unwrap(), "example.com");
+        assert_eq!(
+            VipSender::normalize("*.example.com").unwrap(),
+            "example.com"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        for address in ["", "@", "boss@", "@example", "example", "a b@example.com"] {
+            assert!(VipSender::normalize(address).is_err(), "{}", address);
+        }
+    }
+
+    #[test]
+    fn domains_match_their_subdomains_only() {
+        let vip = vip(None, "@example.com");
+
+        assert!(vip.matches(1, "boss@example.com"));
+        assert!(vip.matches(1, "boss@a.example.com"));
+        assert!(vip.matches(1, "boss@b.a.example.com"));
+        assert!(!vip.matches(1, "boss@badexample.com"));
+        assert!(!vip.matches(1, "boss@example.com.evil.org"));
+        assert!(!vip.matches(1, "example.com"));
+    }
+
+    #[test]
+    fn addresses_match_exactly() {
+        let vip = vip(None, "Boss@Example.com");
+
+        assert!(vip.matches(1, "boss@example.com"));
+        assert!(!vip.matches(1, "boss@a.example.com"));
+        assert!(!vip.matches(1, "other@example.com"));
+    }
+
+    #[test]
+    fn account_entries_only_match_their_account() {
+        let vip = vip(Some(1), "boss@example.com");
+
+        assert!(vip.matches(1, "boss@example.com"));
+        assert!(!vip.matches(2, "boss@example.com"));
+    }
+
+    #[test]
+    fn senders_are_matched_without_display_name_and_case() {
+        let vips = vec![vip(None, "boss@example.com"), vip(Some(2), "@corp.org")];
+        let messages = vec![
+            message(1, "\"The Boss\" <Boss@EXAMPLE.com>"),
+            message(2, "Someone <someone@elsewhere.net>"),
+            message(3, "BOSS@example.com"),
+            message(4, "Team <team@Mail.Corp.org>"),
+            message(5, "Boss <boss@example.com>"),
+        ];
+
+        assert_eq!(vip_messages(&vips, 1, &[1, 2, 3, 4], &messages), vec![1, 3]);
+        assert_eq!(
+            vip_messages(&vips, 2, &[1, 2, 3, 4], &messages),
+            vec![1, 3, 4]
+        );
+    }
+}
@@ -1,59 +1,430 @@
-use flume::Sender;
+use std::{
+    collections::HashMap,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use flume::{Receiver, RecvTimeoutError, Sender};
 use log::{error, info, warn};
-use tokio::task::JoinHandle;
 
-use crate::{imap::Imap, models::Account, UnboundedChannel};
+use crate::{
+    error::{ConnectionError, TlsError},
+    imap::Imap,
+    keychain::Keychain,
+    models::{Account, AuthMethod, ConnectionState, MailboxState, StatusUpdate},
+    oauth, ChannelCmd, UnboundedChannel,
+};
+
+/// Delay before the first reconnection attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Upper bound for the delay between reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Sessions that lasted longer than this are considered healthy and reset the backoff
+const HEALTHY_SESSION: Duration = Duration::from_secs(5 * 60);
+
+/// Jittered exponential backoff between reconnection attempts
+///
+/// Half of the delay is fixed and the other half is random, so accounts
+/// on the same server do not reconnect all at once after an outage.
+#[derive(Debug, Default)]
+struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    fn next_delay(&mut self) -> Duration {
+        let delay = INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(MAX_BACKOFF);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let half = delay / 2;
+        half + half.mul_f64(rand::random::<f64>())
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Cooperative shutdown signal shared by the sessions of an account
+///
+/// The signal is raised when the `Sender` returned by `Shutdown::new` is dropped,
+/// which also wakes up every session sleeping on it.
+#[derive(Debug, Clone)]
+pub struct Shutdown(Receiver<()>);
+
+impl Shutdown {
+    pub fn new() -> (Sender<()>, Self) {
+        let (tx, rx) = flume::bounded(0);
+        (tx, Self(rx))
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.is_disconnected()
+    }
+
+    /// Sleep for `duration` or until shutdown is requested
+    /// Returns true when shutdown was requested
+    pub fn sleep(&self, duration: Duration) -> bool {
+        matches!(
+            self.0.recv_timeout(duration),
+            Err(RecvTimeoutError::Disconnected)
+        )
+    }
+
+    /// Wait until shutdown is requested
+    pub async fn requested(&self) {
+        // Nothing is ever sent, receiving only ends once the sender is dropped
+        let _ = self.0.recv_async().await;
+    }
+}
+
+/// Running sessions of an account
+#[derive(Debug)]
+struct AccountSessions {
+    /// Dropping it asks the sessions to stop
+    shutdown: Sender<()>,
+    /// One dedicated thread per watched mailbox.
+    /// IMAP sessions are blocking (IDLE waits up to 29 minutes), running them
+    /// on the tokio runtime would starve the workers used by sqlx and the commands.
+    threads: Vec<JoinHandle<MailboxState>>,
+}
 
 #[derive(Debug)]
 pub struct Watcher {
     tx: Sender<UnboundedChannel>,
-    threads: Vec<JoinHandle<()>>,
+    /// Running sessions keyed by account id
+    accounts: HashMap<i64, AccountSessions>,
+    /// Number of `stop_account` still waiting for the sessions of an account
+    stopping: HashMap<i64, usize>,
 }
 
 impl Watcher {
     pub fn new(tx: Sender<UnboundedChannel>) -> Self {
         Self {
             tx,
-            threads: vec![],
+            accounts: HashMap::new(),
+            stopping: HashMap::new(),
         }
     }
 
-    pub fn start(&mut self, accounts: Vec<Account>) {
+    /// Start watching every mailbox of the given accounts
+    /// `states` are the last notified messages persisted in the database,
+    /// every session owns the state of its mailbox.
+    pub fn start(&mut self, accounts: Vec<Account>, states: Vec<MailboxState>) {
         info!("Starting watcher with {} accounts.", accounts.len());
 
-        let thread_pool = tokio::runtime::Handle::current();
+        for acc in accounts {
+            self.start_account(acc, &states);
+        }
+    }
 
-        self.threads = accounts
-            .into_iter()
-            .map(|acc| {
+    /// Start watching every mailbox of an account
+    /// Each mailbox uses its own IMAP session, since IDLE only reports
+    /// changes for the currently selected mailbox.
+    /// Sessions already running for the account must be stopped first.
+    /// Inactive accounts, and OAuth2 accounts without tokens, are skipped.
+    pub fn start_account(&mut self, acc: Account, states: &[MailboxState]) {
+        if !acc.active {
+            info!("Account {} is disabled, not watching it", acc.username);
+            for mailbox in &acc.mailboxes {
+                let update = StatusUpdate::new(ConnectionState::Disabled);
+                Watcher::send_status(&self.tx, &acc, mailbox, update);
+            }
+            return;
+        }
+
+        if acc.auth_method != AuthMethod::Password
+            && oauth::load_tokens(&Keychain::new(acc.id, &acc.username)).is_err()
+        {
+            info!(
+                "Account {} is not authorized, not watching it",
+                acc.username
+            );
+            for mailbox in &acc.mailboxes {
+                let update = StatusUpdate {
+                    state: ConnectionState::AuthFailed,
+                    error: Some("Authorize the account to start watching it".to_string()),
+                    retry_in: None,
+                };
+                Watcher::send_status(&self.tx, &acc, mailbox, update);
+            }
+            return;
+        }
+
+        info!("Starting sessions for account {}", acc.username);
+
+        let (shutdown_tx, shutdown) = Shutdown::new();
+        let threads = acc
+            .mailboxes
+            .iter()
+            .filter_map(|mailbox| {
+                let acc = acc.clone();
                 let tx = self.tx.clone();
-                thread_pool.spawn(async move {
-                    Watcher::watch_account(&acc, tx);
-                })
+                let shutdown = shutdown.clone();
+                let state = states
+                    .iter()
+                    .find(|s| s.account_id == acc.id && &s.mailbox == mailbox)
+                    .cloned()
+                    .unwrap_or(MailboxState {
+                        account_id: acc.id,
+                        mailbox: mailbox.clone(),
+                        uid_validity: 0,
+                        last_uid: 0,
+                    });
+                let name = format!("imap-{}-{}", acc.id, mailbox);
+                thread::Builder::new()
+                    .name(name)
+                    .spawn(move || Watcher::watch_mailbox(&acc, state, &shutdown, tx))
+                    .map_err(|e| error!("Failed to spawn watcher thread: {}", e))
+                    .ok()
             })
             .collect();
+
+        self.accounts.insert(
+            acc.id,
+            AccountSessions {
+                shutdown: shutdown_tx,
+                threads,
+            },
+        );
     }
 
-    fn watch_account(acc: &Account, tx: Sender<UnboundedChannel>) {
-        info!("{} - starting", acc.username);
-        let mut imap = Imap::new(Some(acc));
+    /// Watch a mailbox, reconnecting with backoff after transient failures
+    /// Permanent failures (see `ConnectionError`) stop the watcher for this mailbox
+    /// Returns the last notified state once stopped
+    fn watch_mailbox(
+        acc: &Account,
+        mut state: MailboxState,
+        shutdown: &Shutdown,
+        tx: Sender<UnboundedChannel>,
+    ) -> MailboxState {
+        let mailbox = state.mailbox.clone();
+        info!("{} - {} - starting", acc.username, mailbox);
+        let mut imap = Imap::new(Some(acc)).with_shutdown(shutdown.clone());
+        let mut backoff = Backoff::default();
 
-        match imap
-            .connect_account()
-            .and_then(|mut s| imap.check_for_new_messages(&mut s, &tx))
-        {
-            Ok(_) => info!("Finished checking for messages: {}", acc.username),
-            Err(e) => error!(
-                "Error while checking messages for account {}: {:?}",
-                acc.username, e
-            ),
+        loop {
+            let started_at = Instant::now();
+
+            let connecting = StatusUpdate::new(ConnectionState::Connecting);
+            Watcher::send_status(&tx, acc, &mailbox, connecting);
+
+            match imap
+                .connect_account(&mailbox)
+                .and_then(|mut s| imap.check_for_new_messages(&mut s, &mut state, &tx))
+            {
+                Ok(_) => {
+                    info!(
+                        "Finished checking for messages: {} - {}",
+                        acc.username, mailbox
+                    );
+                    break;
+                }
+                Err(_) if shutdown.is_requested() => break,
+                Err(e) if e.downcast_ref::<ConnectionError>().is_some() => {
+                    error!("Giving up on account {} - {}: {}", acc.username, mailbox, e);
+                    let state = match e.downcast_ref::<ConnectionError>() {
+                        Some(ConnectionError::MailboxNotFound(_)) => ConnectionState::Failed,
+                        _ => ConnectionState::AuthFailed,
+                    };
+                    let update = StatusUpdate {
+                        state,
+                        error: Some(e.to_string()),
+                        retry_in: None,
+                    };
+                    Watcher::send_status(&tx, acc, &mailbox, update);
+                    break;
+                }
+                Err(e) => {
+                    if started_at.elapsed() >= HEALTHY_SESSION {
+                        backoff.reset();
+                    }
+                    let delay = backoff.next_delay();
+                    warn!(
+                        "Error while checking messages for account {} - {}: {:?}. Reconnecting in {:?}",
+                        acc.username, mailbox, e, delay
+                    );
+                    let state = match e.downcast_ref::<TlsError>() {
+                        Some(_) => ConnectionState::TlsError,
+                        None => ConnectionState::Reconnecting,
+                    };
+                    let update = StatusUpdate {
+                        state,
+                        error: Some(e.to_string()),
+                        retry_in: Some(delay.as_secs()),
+                    };
+                    Watcher::send_status(&tx, acc, &mailbox, update);
+                    if shutdown.sleep(delay) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        info!("{} - {} - stopped", acc.username, mailbox);
+        state
+    }
+
+    /// Report a transition of a session, see `AccountStatus`
+    fn send_status(
+        tx: &Sender<UnboundedChannel>,
+        acc: &Account,
+        mailbox: &str,
+        update: StatusUpdate,
+    ) {
+        let cmd = ChannelCmd::StatusChanged {
+            mailbox: mailbox.to_string(),
+            update,
+        };
+        if tx.send((cmd, Some(acc.clone()))).is_err() {
+            warn!(
+                "Failed to report the status of {} - {}",
+                acc.username, mailbox
+            );
+        }
+    }
+
+    /// Stop the sessions of an account without waiting for them
+    /// `ChannelCmd::AccountStopped` is sent once they logged out, after every message
+    /// they sent, with their last notified state so it can be persisted before
+    /// new sessions load it
+    pub fn stop_account(&mut self, id: i64) {
+        let sessions: Vec<AccountSessions> = self.accounts.remove(&id).into_iter().collect();
+        if !sessions.is_empty() {
+            info!("Stopping sessions for account {}", id);
+        }
+        *self.stopping.entry(id).or_default() += 1;
+
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let states = Watcher::join(sessions).await;
+            if tx
+                .send((ChannelCmd::AccountStopped { id, states }, None))
+                .is_err()
+            {
+                warn!("Failed to report the stop of account {}", id);
+            }
+        });
+    }
+
+    /// Record the completion of a `stop_account`
+    /// Returns false while other stops of the account are still running
+    pub fn account_stopped(&mut self, id: i64) -> bool {
+        match self.stopping.get_mut(&id) {
+            Some(pending) if *pending > 1 => {
+                *pending -= 1;
+                false
+            }
+            _ => {
+                self.stopping.remove(&id);
+                true
+            }
+        }
+    }
+
+    /// Ask the given sessions to stop and wait for their threads to finish
+    async fn join(sessions: Vec<AccountSessions>) -> Vec<MailboxState> {
+        let threads: Vec<JoinHandle<MailboxState>> = sessions
+            .into_iter()
+            .flat_map(|sessions| sessions.threads)
+            .collect();
+
+        // Senders are dropped above, joining blocks until every session noticed it
+        let joined = tokio::task::spawn_blocking(move || {
+            threads
+                .into_iter()
+                .filter_map(|thread| {
+                    thread
+                        .join()
+                        .map_err(|_| error!("Watcher thread panicked"))
+                        .ok()
+                })
+                .collect()
+        })
+        .await;
+
+        joined.unwrap_or_else(|e| {
+            error!("Failed to wait for the watcher threads: {}", e);
+            vec![]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Session that reports a status while logging out
+    fn session(
+        tx: &Sender<UnboundedChannel>,
+        shutdown: Shutdown,
+        last_uid: i64,
+    ) -> JoinHandle<MailboxState> {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            while !shutdown.sleep(Duration::from_secs(1)) {}
+            thread::sleep(Duration::from_millis(100));
+            let cmd = ChannelCmd::StatusChanged {
+                mailbox: "INBOX".to_string(),
+                update: StatusUpdate::new(ConnectionState::Idle),
+            };
+            tx.send((cmd, None)).unwrap();
+            MailboxState {
+                account_id: 1,
+                mailbox: "INBOX".to_string(),
+                uid_validity: 1,
+                last_uid,
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn stopped_sessions_are_reported_after_their_messages() {
+        let (tx, rx) = flume::unbounded();
+        let mut watcher = Watcher::new(tx.clone());
+        let (stop, shutdown) = Shutdown::new();
+        let thread = session(&tx, shutdown, 42);
+        watcher.accounts.insert(
+            1,
+            AccountSessions {
+                shutdown: stop,
+                threads: vec![thread],
+            },
+        );
+
+        watcher.stop_account(1);
+        assert!(watcher.accounts.is_empty());
+
+        let (first, _) = rx.recv_async().await.unwrap();
+        assert!(matches!(first, ChannelCmd::StatusChanged { .. }));
+        let (second, _) = rx.recv_async().await.unwrap();
+        match second {
+            ChannelCmd::AccountStopped { id, states } => {
+                assert_eq!(id, 1);
+                assert_eq!(states[0].last_uid, 42);
+            }
+            other => panic!("unexpected {:?}", other),
         }
+        assert!(watcher.account_stopped(1));
     }
 
-    pub fn shutdown(&mut self) {
-        warn!("shutting down watcher");
-        for thread in self.threads.drain(..) {
-            thread.abort();
+    #[tokio::test]
+    async fn account_restarts_once_every_stop_completed() {
+        let (tx, rx) = flume::unbounded();
+        let mut watcher = Watcher::new(tx);
+
+        watcher.stop_account(1);
+        watcher.stop_account(1);
+        for _ in 0..2 {
+            let (cmd, _) = rx.recv_async().await.unwrap();
+            assert!(matches!(cmd, ChannelCmd::AccountStopped { id: 1, .. }));
         }
+
+        assert!(!watcher.account_stopped(1));
+        assert!(watcher.account_stopped(1));
     }
 }
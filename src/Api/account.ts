@@ -1,5 +1,6 @@
-import type { Account } from "@/Models";
+import type { Account, AccountStatus } from "@/Models";
 import { invoke } from "@tauri-apps/api";
+import { Event as TauriEvent, listen, UnlistenFn } from "@tauri-apps/api/event";
 
 export function updateAccount(
   id: number,
@@ -12,6 +13,10 @@ export function deleteAccount(id: number): Promise<void> {
   return invoke<void>("cmd_delete_account", { id });
 }
 
+export function setAccountActive(id: number, active: boolean): Promise<void> {
+  return invoke<void>("cmd_set_account_active", { id, active });
+}
+
 export function findAccountById(id: number): Promise<Account | undefined> {
   return invoke<Account | undefined>("cmd_find_account", { id });
 }
@@ -20,6 +25,32 @@ export function allAccounts(): Promise<Account[]> {
   return invoke<Account[]>("cmd_list_accounts");
 }
 
-export function createAccount(attrs: Omit<Account, "id">): Promise<string> {
-  return invoke<string>("cmd_create_account", { attrs });
+export function authorizeAccount(id: number): Promise<void> {
+  return invoke<void>("cmd_authorize_account", { id });
+}
+
+export function createAccount(attrs: Omit<Account, "id">): Promise<number> {
+  return invoke<number>("cmd_create_account", { attrs });
+}
+
+export function accountStatuses(): Promise<AccountStatus[]> {
+  return invoke<AccountStatus[]>("cmd_account_statuses");
+}
+
+export function onAccountStatus(
+  cb: (status: AccountStatus) => void
+): Promise<UnlistenFn> {
+  return listen<AccountStatus>(
+    "account_status",
+    ({ payload }: TauriEvent<AccountStatus>) => cb(payload)
+  );
+}
+
+export function onAccountStatusRemoved(
+  cb: (accountId: number) => void
+): Promise<UnlistenFn> {
+  return listen<number>(
+    "account_status_removed",
+    ({ payload }: TauriEvent<number>) => cb(payload)
+  );
 }
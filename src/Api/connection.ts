@@ -1,15 +1,15 @@
 import { Event as TauriEvent, listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api";
-import type { ConnectionCreds } from "@/Models";
+import type { ConnectionCreds, ConnectionTestResult } from "@/Models";
 
 export type UnListenConnectionFn = () => Promise<void>;
 
 export async function onTestConnectionResponse(
-  cb: (payload: string) => void
+  cb: (payload: ConnectionTestResult) => void
 ): Promise<UnListenConnectionFn> {
-  const unlistenFn = await listen<string>(
+  const unlistenFn = await listen<ConnectionTestResult>(
     "connection_test_result",
-    ({ payload }: TauriEvent<string>) => {
+    ({ payload }: TauriEvent<ConnectionTestResult>) => {
       return cb(payload);
     }
   );
@@ -21,6 +21,13 @@ export async function onTestConnectionResponse(
   };
 }
 
-export function testConnection(attrs: ConnectionCreds): Promise<string> {
-  return invoke<string>("cmd_test_connection", { attrs });
+export function testConnection(
+  requestId: string,
+  attrs: ConnectionCreds
+): Promise<void> {
+  return invoke<void>("cmd_test_connection", { requestId, attrs });
+}
+
+export function cancelConnectionTest(requestId: string): Promise<void> {
+  return invoke<void>("cmd_cancel_connection_test", { requestId });
 }
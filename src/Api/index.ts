@@ -3,9 +3,36 @@ export {
   findAccountById,
   allAccounts,
   deleteAccount,
+  setAccountActive,
   updateAccount,
+  authorizeAccount,
+  accountStatuses,
+  onAccountStatus,
+  onAccountStatusRemoved,
 } from "./account";
 
-export { testConnection, onTestConnectionResponse } from "./connection";
+export {
+  testConnection,
+  cancelConnectionTest,
+  onTestConnectionResponse,
+} from "./connection";
 export type { UnListenConnectionFn } from "./connection";
-export { updateSettings, fetchSettings } from "./settings";
+export {
+  updateSettings,
+  fetchSettings,
+  pauseNotifications,
+  resumeNotifications,
+  onNotificationsPaused,
+} from "./settings";
+export {
+  fetchAccountPreferences,
+  updateAccountPreferences,
+} from "./preferences";
+export {
+  allRules,
+  createRule,
+  updateRule,
+  deleteRule,
+  testRule,
+} from "./rules";
+export { allVipSenders, createVipSender, deleteVipSender } from "./vip";
@@ -0,0 +1,17 @@
+import type { AccountPreferences } from "@/Models";
+import { invoke } from "@tauri-apps/api";
+
+export function fetchAccountPreferences(
+  accountId: number
+): Promise<AccountPreferences> {
+  return invoke<AccountPreferences>("cmd_fetch_account_preferences", {
+    accountId,
+  });
+}
+
+export function updateAccountPreferences(
+  accountId: number,
+  attrs: Omit<AccountPreferences, "account_id">
+): Promise<void> {
+  return invoke<void>("cmd_update_account_preferences", { accountId, attrs });
+}
@@ -0,0 +1,25 @@
+import type { MessageHeaders, Rule, RuleAttrs } from "@/Models";
+import { invoke } from "@tauri-apps/api";
+
+export function allRules(): Promise<Rule[]> {
+  return invoke<Rule[]>("cmd_list_rules");
+}
+
+export function createRule(attrs: RuleAttrs): Promise<Rule> {
+  return invoke<Rule>("cmd_create_rule", { attrs });
+}
+
+export function updateRule(id: number, attrs: RuleAttrs): Promise<void> {
+  return invoke<void>("cmd_update_rule", { id, attrs });
+}
+
+export function deleteRule(id: number): Promise<void> {
+  return invoke<void>("cmd_delete_rule", { id });
+}
+
+export function testRule(
+  attrs: RuleAttrs,
+  headers: MessageHeaders
+): Promise<boolean> {
+  return invoke<boolean>("cmd_test_rule", { attrs, headers });
+}
@@ -1,5 +1,6 @@
-import type { Settings } from "@/Models";
+import type { PauseDuration, Settings } from "@/Models";
 import { invoke } from "@tauri-apps/api";
+import { Event as TauriEvent, listen, UnlistenFn } from "@tauri-apps/api/event";
 
 export async function updateSettings(
   attrs: Settings
@@ -10,3 +11,20 @@ export async function updateSettings(
 export async function fetchSettings(): Promise<Settings | null> {
   return invoke<Settings | null>("cmd_fetch_settings");
 }
+
+export function pauseNotifications(duration: PauseDuration): Promise<number> {
+  return invoke<number>("cmd_pause_notifications", { duration });
+}
+
+export function resumeNotifications(): Promise<void> {
+  return invoke<void>("cmd_resume_notifications");
+}
+
+export function onNotificationsPaused(
+  cb: (pausedUntil: number | null) => void
+): Promise<UnlistenFn> {
+  return listen<number | null>(
+    "notifications_paused",
+    ({ payload }: TauriEvent<number | null>) => cb(payload)
+  );
+}
@@ -0,0 +1,16 @@
+import type { VipSender } from "@/Models";
+import { invoke } from "@tauri-apps/api";
+
+export function allVipSenders(): Promise<VipSender[]> {
+  return invoke<VipSender[]>("cmd_list_vip_senders");
+}
+
+export function createVipSender(
+  attrs: Omit<VipSender, "id">
+): Promise<VipSender> {
+  return invoke<VipSender>("cmd_create_vip_sender", { attrs });
+}
+
+export function deleteVipSender(id: number): Promise<void> {
+  return invoke<void>("cmd_delete_vip_sender", { id });
+}
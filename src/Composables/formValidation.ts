@@ -61,6 +61,19 @@ function isNumber(msg: string = "Field is not a number"): RuleFunction {
   ];
 }
 
+// Rule to check if a number is at least `min`
+function min(
+  min: number,
+  msg: string = `Must be at least ${min}`
+): RuleFunction {
+  return [
+    "min",
+    (input: any): RuleResponse => {
+      return [Number(input) >= min, msg];
+    },
+  ];
+}
+
 function requiredIf(
   condition: boolean,
   msg: string = "Field is required"
@@ -113,6 +126,7 @@ export function useFormValidation(): FormValidation {
     rules: {
       required,
       isNumber,
+      min,
       requiredIf,
     },
     validate(
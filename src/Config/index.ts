@@ -1,5 +1,11 @@
 export const baseUrl = import.meta.env.BASE_URL;
 
+export enum Security {
+  TLS = "tls",
+  STARTTLS = "starttls",
+  NONE = "none",
+}
+
 export enum Color {
   SKY = "sky",
   RED = "red",
@@ -1,5 +1,7 @@
+import type { AuthMethod, OAuthProvider, Security } from "./connection";
+
 export type Account = {
-  id: string;
+  id: number;
   name: string;
   server: string;
   port: number;
@@ -7,5 +9,10 @@ export type Account = {
   active: boolean;
   username: string;
   mailbox: string;
+  security: Security;
+  auth_method: AuthMethod;
+  oauth_provider?: OAuthProvider | null;
+  poll_interval: number;
+  mailboxes: string[];
   password?: string;
 };
@@ -1,7 +1,59 @@
+export type Security = "tls" | "starttls" | "none";
+
+export type AuthMethod = "password" | "xoauth2" | "oauthbearer";
+
+export type OAuthProvider = "google" | "microsoft";
+
 export type ConnectionCreds = {
   server: string;
   port: number;
   username: string;
   password: string;
   mailbox: string;
+  security: Security;
+  auth_method: AuthMethod;
+  oauth_provider?: OAuthProvider | null;
+  account_id?: number;
+};
+
+export type WatchMode = "idle" | "polling";
+
+export type TestStage =
+  | "dns"
+  | "tcp"
+  | "tls"
+  | "greeting"
+  | "authentication"
+  | "capabilities"
+  | "select";
+
+export type TestStep = {
+  stage: TestStage;
+  ok: boolean;
+  detail: string;
+  elapsed_ms: number;
+};
+
+export type CertificateInfo = {
+  subject: string;
+  issuer: string;
+  not_before: number;
+  not_after: number;
+  dns_names: string[];
+  fingerprint: string;
+};
+
+export type ConnectionReport = {
+  steps: TestStep[];
+  certificate: CertificateInfo | null;
+  capabilities: string[];
+  watch_mode: WatchMode | null;
+  messages: number | null;
+  unseen: number | null;
+};
+
+export type ConnectionTestResult = {
+  request_id: string;
+  report: ConnectionReport;
+  cancelled: boolean;
 };
@@ -1,3 +1,25 @@
-export type { ConnectionCreds } from "./connection";
+export type {
+  AuthMethod,
+  CertificateInfo,
+  ConnectionCreds,
+  ConnectionReport,
+  ConnectionTestResult,
+  OAuthProvider,
+  Security,
+  TestStage,
+  TestStep,
+  WatchMode,
+} from "./connection";
 export type { Account } from "./account";
-export type { Settings } from "./settings";
+export type { PauseDuration, Settings } from "./settings";
+export type { AccountPreferences } from "./preferences";
+export type { MessageHeaders } from "./message";
+export type {
+  MatchType,
+  Rule,
+  RuleAction,
+  RuleAttrs,
+  RuleField,
+} from "./rules";
+export type { VipSender } from "./vip";
+export type { AccountStatus, ConnectionState } from "./status";
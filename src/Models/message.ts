@@ -0,0 +1,7 @@
+export type MessageHeaders = {
+  uid?: number;
+  from: string;
+  to: string;
+  subject: string;
+  list_id: string;
+};
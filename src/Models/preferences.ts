@@ -0,0 +1,7 @@
+export type AccountPreferences = {
+  account_id?: number;
+  muted?: boolean | null;
+  sound?: boolean | null;
+  preview?: boolean | null;
+  sound_path?: string | null;
+};
@@ -0,0 +1,18 @@
+export type RuleField = "from" | "to" | "subject" | "list_id";
+export type MatchType = "contains" | "regex";
+export type RuleAction = "ignore" | "notify" | "urgent" | "sound";
+
+export type Rule = {
+  id: number;
+  account_id: number | null;
+  name: string;
+  field: RuleField;
+  match_type: MatchType;
+  pattern: string;
+  action: RuleAction;
+  sound_path: string | null;
+  position: number;
+  enabled: boolean;
+};
+
+export type RuleAttrs = Omit<Rule, "id">;
@@ -2,4 +2,15 @@ export type Settings = {
   notifications?: boolean;
   sound?: boolean;
   preview?: boolean;
+  volume?: number;
+  paused_until?: number | null;
+  quiet_hours?: boolean;
+  quiet_start?: string;
+  quiet_end?: string;
+  quiet_days?: number;
+  quiet_summary?: boolean;
+  coalesce_window?: number;
+  rate_limit?: number;
 };
+
+export type PauseDuration = "thirty_minutes" | "one_hour" | "until_tomorrow";
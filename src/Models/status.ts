@@ -0,0 +1,20 @@
+export type ConnectionState =
+  | "connecting"
+  | "idle"
+  | "polling"
+  | "reconnecting"
+  | "auth_failed"
+  | "tls_error"
+  | "failed"
+  | "disabled";
+
+export type AccountStatus = {
+  account_id: number;
+  mailbox: string;
+  state: ConnectionState;
+  since: number;
+  retry_at: number | null;
+  last_checked: number | null;
+  last_error: string | null;
+  last_error_at: number | null;
+};
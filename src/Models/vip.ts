@@ -0,0 +1,5 @@
+export type VipSender = {
+  id: number;
+  account_id: number | null;
+  address: string;
+};
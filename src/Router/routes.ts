@@ -2,6 +2,7 @@ import { RouteRecordRaw } from "vue-router";
 import AccountsView from "@/Views/Accounts.vue";
 import AccountFormView from "@/Views/AccountForm.vue";
 import SettingsView from "@/Views/Settings.vue";
+import RulesView from "@/Views/Rules.vue";
 
 export const routes: RouteRecordRaw[] = [
   {
@@ -24,4 +25,9 @@ export const routes: RouteRecordRaw[] = [
     name: "settings",
     component: SettingsView,
   },
+  {
+    path: "/settings/rules",
+    name: "rules",
+    component: RulesView,
+  },
 ];